@@ -46,7 +46,7 @@ fn main() -> anyhow::Result<()> {
     match args.command {
         Some(Commands::Create { file, method }) => {
             let config = read_config(&file)?;
-            let karabiner_config = convert_configuration(&config);
+            let karabiner_config = convert_configuration(&config)?;
 
             match method {
                 Method::Extend => extend_config(karabiner_config),
@@ -76,7 +76,7 @@ fn replace_karabiner_config(karabiner_config: KarabinerConfig) -> anyhow::Result
     Ok(())
 }
 
-fn extend_config(karabiner_config: KarabinerConfig) -> anyhow::Result<()> {
+fn extend_config(_karabiner_config: KarabinerConfig) -> anyhow::Result<()> {
     todo!()
 }
 
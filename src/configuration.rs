@@ -1,32 +1,110 @@
+use std::str::FromStr;
+
 use anyhow::{anyhow, Context as _, Result};
 use serde::{Deserialize, Serialize};
 use toml::Value;
 
-use crate::keys::Key;
+use crate::keys::{self, Key, KeyCode, KeyCombo, KeyboardLayout, StickyModifierMode};
 
 pub const BASE_LAYER: &str = "baselayer";
+/// Separates the name of a sublayer from the name of its parent layer.
+pub const SUBLAYER_SEPARATOR: char = '.';
 
 #[derive(Debug, Clone)]
 pub struct Configuration {
+    pub settings: Settings,
     pub simple_remaps: SimpleRemaps,
     pub layers: Layers,
     pub layer_assignments: LayerAssignments,
+    pub home_row_mods: HomeRowMods,
+}
+
+/// Global settings. They are given as plain values at the top level of the configuration.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct Settings {
+    /// Stores the active layer in a single variable so that activating a layer leaves all others.
+    pub exclusive_layers: bool,
+    /// Keys which leave all layers and return to the base layer, no matter which layers are active.
+    pub reset: Option<Vec<KeyCode>>,
+    /// Layout which is used to type text.
+    pub keyboard_layout: KeyboardLayout,
+    /// Shows a notification with the name of the active layer.
+    pub notifications: bool,
+    /// Milliseconds within which the keys of a combo have to be pressed.
+    pub simultaneous_threshold: Option<u32>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SimpleRemap {
-    pub from: Key,
-    pub to: Vec<Key>,
+    pub from: KeyCode,
+    pub to: Vec<KeyCode>,
+    pub options: KeyOptions,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SimpleRemaps {
     pub remaps: Vec<SimpleRemap>,
 }
+/// Keys which type as usual when tapped and act as modifier when held down.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct HomeRowMods {
+    pub keys: Vec<HomeRowMod>,
+    /// Milliseconds a key has to be held down before it acts as modifier.
+    pub hold_timeout: u32,
+    /// Milliseconds after a typed character during which the keys only type.
+    pub typing_streak_timeout: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct HomeRowMod {
+    pub key: Key,
+    pub modifier: Key,
+    /// Overrides the hold timeout of the block for this key.
+    pub hold_timeout: Option<u32>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct Layer {
     pub name: String,
-    pub keys: Vec<Key>,
+    pub keys: KeyCombo,
+    pub settings: LayerSettings,
+}
+
+/// Settings of a layer. They are given as plain values inside the table of the layer, next to the
+/// key bindings.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct LayerSettings {
+    pub unmapped: UnmappedKeys,
+    /// Bindings of layers with a higher priority take precedence when several layers are active.
+    pub priority: i32,
+    /// Milliseconds after which the layer is left again if no key is pressed after entering it.
+    pub timeout: Option<u32>,
+    /// Overrides the global `notifications` setting for the layer.
+    pub notification: Option<Notification>,
+    /// Default for `pass_modifiers` of the bindings in the layer.
+    pub pass_modifiers: bool,
+}
+
+/// Notification which is shown while a layer is active.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub enum Notification {
+    Hidden,
+    LayerName,
+    Label(String),
+}
+
+/// Behavior of keys which are not bound in a layer.
+#[derive(
+    Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq, strum::Display, strum::EnumString,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum UnmappedKeys {
+    /// The key is sent as usual and the layer stays active.
+    #[default]
+    Passthrough,
+    /// The layer is left and the key is sent as usual.
+    ExitAndPassthrough,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -46,23 +124,169 @@ pub struct LayerShift {
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct LayerRemap {
-    pub to: Vec<Key>,
+    pub to: Vec<KeyCode>,
+    pub options: KeyOptions,
+}
+
+/// Flags of the keys which are sent by a remap.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct KeyOptions {
+    /// Whether the key repeats while it is held down.
+    pub repeat: Option<bool>,
+    /// Sends modifiers only together with the next key instead of right away.
+    pub lazy: Option<bool>,
+    /// Sets [`ToEventOptions::halt`](crate::karabiner::ToEventOptions::halt) of the sent keys.
+    pub halt: Option<bool>,
+    /// Keeps the key pressed for the given time.
+    pub hold_down_milliseconds: Option<u32>,
+}
+
+/// Sequence of key combinations which are sent one after another.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Macro {
+    pub steps: Vec<MacroStep>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct MacroStep {
+    pub keys: Vec<KeyCode>,
+    /// Delays the next step by keeping the keys pressed for the given time.
+    pub hold_down_milliseconds: Option<u32>,
+}
+
+/// Text which is typed by sending the keys of its characters.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct TypeText {
+    pub text: String,
+}
+
+/// Application which is opened by name, bundle identifier or path.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, derive_more::Display)]
+pub enum OpenApp {
+    Name(String),
+    BundleId(String),
+    FilePath(String),
+}
+
+/// URL which is opened with the default application for its scheme.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct OpenUrl {
+    pub url: String,
+}
+
+/// AppleScript which is run with `osascript`.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub enum AppleScript {
+    Inline(String),
+    File(String),
+}
+
+/// Shortcut of the Shortcuts app which is run by its name.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Shortcut {
+    pub name: String,
+}
+
+/// Runs `double` instead of a second `single` when the key is pressed again within the timeout.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct DoubleTap {
+    pub single: Box<Action>,
+    pub double: Box<Action>,
+    /// Milliseconds within which the second press has to follow the first one.
+    pub timeout: u32,
+}
+
+/// Runs `tap` when the key is released quickly and `hold` once it has been held down for the threshold.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct LongPress {
+    pub tap: Box<Action>,
+    pub hold: Box<Action>,
+    /// Milliseconds after which the key counts as held down.
+    pub threshold: u32,
+}
+
+/// Key which sends `tap` when it is pressed alone and `hold` while it is held down.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct TapHold {
+    pub tap: Vec<KeyCode>,
+    pub hold: Vec<KeyCode>,
+    /// Milliseconds after which the key counts as held down.
+    pub timeout: u32,
+    /// Flags of the hold keys.
+    pub options: KeyOptions,
+}
+
+/// Input source which is selected by its language or identifier.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct SelectInputSource {
+    pub language: Option<String>,
+    pub input_source_id: Option<String>,
+}
+
+/// Modifier which stays pressed for the next key.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Sticky {
+    pub modifier: Key,
+    pub mode: StickyModifierMode,
+}
+
+/// Mouse event which is sent while the key is pressed.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, derive_more::Display)]
+pub enum Mouse {
+    #[display("move {direction}")]
+    Move { direction: Direction, speed: u32 },
+    #[display("scroll {direction}")]
+    Scroll { direction: Direction, speed: u32 },
+    /// Pointing button such as `button1` for the left button.
+    #[display("click {_0}")]
+    Click(String),
+    /// Scales the speed of the other mouse events while the key is pressed.
+    #[display("speed x{_0}")]
+    SpeedMultiplier(serde_json::Number),
+}
+
+#[derive(
+    Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, strum::Display, strum::EnumString,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum Direction {
+    Left,
+    Right,
+    Up,
+    Down,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub enum Action {
     Command(Command),
     LayerRemap(LayerRemap),
+    Macro(Macro),
+    TypeText(TypeText),
+    OpenApp(OpenApp),
+    OpenUrl(OpenUrl),
+    AppleScript(AppleScript),
+    Shortcut(Shortcut),
+    SelectInputSource(SelectInputSource),
+    Mouse(Mouse),
+    TapHold(TapHold),
+    DoubleTap(DoubleTap),
+    LongPress(LongPress),
+    Sticky(Sticky),
     LayerShift(LayerShift),
+    /// The key falls through to the next active layer with lower priority.
+    Transparent,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct LayerAssignment {
     pub layer: Layer,
-    pub key: Key,
+    pub key: KeyCombo,
     pub action: Action,
     pub next_layer: Option<String>,
     pub description: Option<String>,
+    /// Modifiers which are held while pressing the key are passed on to the action.
+    pub pass_modifiers: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
@@ -76,20 +300,38 @@ impl SimpleRemaps {
             .as_table()
             .context("Invalid remaps format")?
             .iter()
-            .map(|(from_key, to_key)| {
-                let from = Key::try_from(from_key.as_str())
+            .filter(|(_, v)| !v.is_table() || v.get("remap").is_some())
+            .map(|(from_key, to_value)| {
+                let from = KeyCode::from_str(from_key)
+                    .ok()
+                    .filter(|k| !k.is_modifier_only())
                     .with_context(|| format!("Invalid key in remaps: {}", from_key))?;
-                let to = match to_key {
-                    Value::String(to_str) => vec![Key::try_from(to_str.as_str())
-                        .with_context(|| format!("Invalid value in remaps: {}", to_str))?],
+                let (to_str, options) = match to_value {
+                    Value::String(to_str) => (to_str.as_str(), KeyOptions::default()),
+                    Value::Table(_) => {
+                        check_keys(to_value, &[&["remap"], &KeyOptions::KEYS], "remap")?;
+                        (
+                            to_value
+                                .get("remap")
+                                .and_then(|v| v.as_str())
+                                .with_context(|| {
+                                    format!("Expected string for remap of {}", from_key)
+                                })?,
+                            KeyOptions::from_toml(to_value)?,
+                        )
+                    }
                     _ => {
                         return Err(anyhow::anyhow!(
-                            "Expected string for remap value, got: {:?}",
-                            to_key
+                            "Expected string or table for remap value, got: {:?}",
+                            to_value
                         ))
                     }
                 };
-                Ok(SimpleRemap { from, to })
+                let to = vec![KeyCode::from_str(to_str)
+                    .ok()
+                    .filter(|k| !k.is_modifier_only())
+                    .with_context(|| format!("Invalid value in remaps: {}", to_str))?];
+                Ok(SimpleRemap { from, to, options })
             })
             .collect::<Result<Vec<SimpleRemap>>>()?;
         Ok(SimpleRemaps { remaps })
@@ -103,69 +345,408 @@ impl Layers {
             .context("Invalid layers format")?
             .iter()
             .map(|(layer_name, layer_keys)| {
+                if layer_name.contains(SUBLAYER_SEPARATOR) {
+                    return Err(anyhow!(
+                        "Layer names must not contain '{}': {}",
+                        SUBLAYER_SEPARATOR,
+                        layer_name
+                    ));
+                }
                 let keys_str = layer_keys.as_str().context(format!(
                     "Expected string for layer keys, got: {:?}",
                     layer_keys
                 ))?;
 
                 let keys = keys_str
-                    .split('+')
-                    .map(|key| {
-                        Key::try_from(key).with_context(|| format!("Invalid key in layer: {}", key))
-                    })
-                    .collect::<Result<Vec<Key>>>()?;
+                    .parse::<KeyCombo>()
+                    .map_err(|_| anyhow!("Invalid keys for layer: {}", keys_str))?;
 
                 Ok(Layer {
                     name: layer_name.to_string(),
                     keys,
+                    settings: LayerSettings::default(),
                 })
             })
             .collect::<Result<Vec<Layer>>>()?;
         Ok(Layers { layers })
     }
+
+    /// Returns the layers ordered by precedence, highest first. Layers with the same priority are
+    /// ordered by name and the base layer always comes last.
+    pub fn by_priority(&self) -> Vec<&Layer> {
+        let mut layers: Vec<&Layer> = self.layers.iter().collect();
+        layers.sort_by_key(|l| (l.name == BASE_LAYER, -l.settings.priority, l.name.as_str()));
+        layers
+    }
+}
+
+impl Layer {
+    /// Sublayers are defined by nested tables inside of another layer and are entered from there.
+    pub fn is_sublayer(&self) -> bool {
+        self.name.contains(SUBLAYER_SEPARATOR)
+    }
+
+    fn sublayer_name(&self, key: &str) -> String {
+        format!("{}{}{}", self.name, SUBLAYER_SEPARATOR, key)
+    }
 }
 
 impl Configuration {
     pub fn from_toml(value: &Value) -> Result<Self> {
-        let remaps = value
+        let settings = Settings::from_toml(value).context("Invalid settings")?;
+
+        let remaps_value = value
             .get("simple_remaps")
             .context("Missing remaps in configuration")?;
-        let remaps = SimpleRemaps::from_toml(remaps)?;
+        let remaps = SimpleRemaps::from_toml(remaps_value)?;
 
         let layers = value
             .get("layers")
             .context("Missing layers in configuration")?;
         let layers = Layers::from_toml(layers)?;
         let layers = Self::add_base_layer(layers);
-
-        let layer_assignments = LayerAssignments::from_toml(value, layers.layers.clone())?;
+        let layers = Self::add_sublayers(layers, value)?;
+        let layers = Self::add_layer_settings(layers, value)?;
+
+        let mut layer_assignments = LayerAssignments::from_toml(value, layers.layers.clone())?;
+        layer_assignments
+            .assignments
+            .extend(LayerAssignment::from_simple_remaps(
+                remaps_value,
+                &layers.layers[0],
+            )?);
+
+        let home_row_mods = value
+            .get("home_row_mods")
+            .map(HomeRowMods::from_toml)
+            .transpose()
+            .context("Invalid home_row_mods")?
+            .unwrap_or_default();
 
         Ok(Configuration {
+            settings,
             simple_remaps: remaps,
             layers,
             layer_assignments,
+            home_row_mods,
         })
     }
 
     fn add_base_layer(mut layers: Layers) -> Layers {
         let base_layer = Layer {
             name: BASE_LAYER.to_string(),
-            keys: Vec::new(),
+            keys: KeyCombo::default(),
+            settings: LayerSettings::default(),
         };
         layers.layers.insert(0, base_layer);
         layers
     }
+
+    fn add_sublayers(mut layers: Layers, value: &Value) -> Result<Layers> {
+        let mut index = 0;
+        while index < layers.layers.len() {
+            let layer = layers.layers[index].clone();
+            let layer_value = LayerAssignments::get_assignments_for_layer(&layer.name, value)?;
+            let table = layer_value
+                .as_table()
+                .ok_or_else(|| anyhow!("Invalid TOML format"))?;
+            for (key, _) in table.iter().filter(|(_, v)| Action::is_sublayer(v)) {
+                layers.layers.push(Layer {
+                    name: layer.sublayer_name(key),
+                    keys: KeyCombo::default(),
+                    settings: LayerSettings::default(),
+                });
+            }
+            index += 1;
+        }
+        Ok(layers)
+    }
+
+    fn add_layer_settings(mut layers: Layers, value: &Value) -> Result<Layers> {
+        for layer in layers.layers.iter_mut() {
+            let layer_value = LayerAssignments::get_assignments_for_layer(&layer.name, value)?;
+            layer.settings = LayerSettings::from_toml(&layer_value)
+                .with_context(|| format!("Invalid settings in layer: {}", layer.name))?;
+        }
+        Ok(layers)
+    }
+}
+
+impl Default for HomeRowMods {
+    fn default() -> Self {
+        HomeRowMods {
+            keys: vec![],
+            hold_timeout: 200,
+            typing_streak_timeout: 150,
+        }
+    }
+}
+
+impl HomeRowMods {
+    /// Plain values are either settings of the block or the modifier of a key. Tables configure a
+    /// key with its own settings.
+    pub fn from_toml(value: &Value) -> Result<Self> {
+        let mut home_row_mods = HomeRowMods::default();
+        let table = value
+            .as_table()
+            .ok_or_else(|| anyhow!("Expected table for home_row_mods, got: {:?}", value))?;
+
+        for (name, value) in table.iter() {
+            match name.as_str() {
+                "hold_timeout" => home_row_mods.hold_timeout = parse_milliseconds(name, value)?,
+                "typing_streak_timeout" => {
+                    home_row_mods.typing_streak_timeout = parse_milliseconds(name, value)?
+                }
+                _ => home_row_mods.keys.push(HomeRowMod::from_toml(name, value)?),
+            }
+        }
+
+        Ok(home_row_mods)
+    }
+}
+
+impl HomeRowMod {
+    fn from_toml(key: &str, value: &Value) -> Result<Self> {
+        let (modifier, hold_timeout) = match value {
+            Value::String(modifier) => (modifier.as_str(), None),
+            Value::Table(table) => {
+                let modifier = table
+                    .get("hold")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow!("Missing hold for home row key {}", key))?;
+                let hold_timeout = table
+                    .get("hold_timeout")
+                    .map(|v| parse_milliseconds("hold_timeout", v))
+                    .transpose()?;
+                (modifier, hold_timeout)
+            }
+            _ => {
+                return Err(anyhow!(
+                    "Expected modifier or table for home row key {}, got: {:?}",
+                    key,
+                    value
+                ))
+            }
+        };
+
+        let key = key
+            .parse::<Key>()
+            .ok()
+            .filter(|k| !k.is_modifier_only())
+            .ok_or_else(|| anyhow!("Invalid home row key: {}", key))?;
+        let modifier = modifier
+            .parse::<Key>()
+            .ok()
+            .filter(|k| k.is_modifier() && !k.is_modifier_only())
+            .ok_or_else(|| anyhow!("Invalid modifier for home row key {}: {}", key, modifier))?;
+
+        Ok(HomeRowMod {
+            key,
+            modifier,
+            hold_timeout,
+        })
+    }
+}
+
+fn parse_milliseconds(name: &str, value: &Value) -> Result<u32> {
+    value
+        .as_integer()
+        .and_then(|ms| u32::try_from(ms).ok())
+        .ok_or_else(|| anyhow!("Invalid {}: {:?}", name, value))
+}
+
+/// Fails for keys of the table which are in none of the lists, such as misspelled options.
+fn check_keys(value: &Value, keys: &[&[&str]], kind: &str) -> Result<()> {
+    let unknown = value.as_table().and_then(|table| {
+        table
+            .keys()
+            .find(|k| !keys.iter().any(|keys| keys.contains(&k.as_str())))
+    });
+    match unknown {
+        Some(unknown) => Err(anyhow!("Unknown option for {}: {}", kind, unknown)),
+        None => Ok(()),
+    }
+}
+
+impl Settings {
+    pub fn from_toml(value: &Value) -> Result<Self> {
+        let mut settings = Settings::default();
+        let table = value
+            .as_table()
+            .ok_or_else(|| anyhow!("Invalid TOML format"))?;
+
+        for (name, value) in table.iter().filter(|(_, v)| !v.is_table()) {
+            match name.as_str() {
+                "exclusive_layers" => {
+                    settings.exclusive_layers = value.as_bool().ok_or_else(|| {
+                        anyhow!("Expected boolean for exclusive_layers, got: {:?}", value)
+                    })?;
+                }
+                "reset" => {
+                    let reset = value
+                        .as_str()
+                        .ok_or_else(|| anyhow!("Expected string for reset, got: {:?}", value))?;
+                    settings.reset = Some(Action::parse_keys(reset)?);
+                }
+                "keyboard_layout" => {
+                    let layout = value.as_str().ok_or_else(|| {
+                        anyhow!("Expected string for keyboard_layout, got: {:?}", value)
+                    })?;
+                    settings.keyboard_layout = layout
+                        .parse()
+                        .map_err(|_| anyhow!("Unknown keyboard layout: {}", layout))?;
+                }
+                "simultaneous_threshold" => {
+                    settings.simultaneous_threshold = Some(parse_milliseconds(name, value)?);
+                }
+                "notifications" => {
+                    settings.notifications = value.as_bool().ok_or_else(|| {
+                        anyhow!("Expected boolean for notifications, got: {:?}", value)
+                    })?;
+                }
+                _ => return Err(anyhow!("Unknown setting: {}", name)),
+            }
+        }
+
+        Ok(settings)
+    }
+}
+
+impl LayerSettings {
+    pub fn from_toml(value: &Value) -> Result<Self> {
+        let mut settings = LayerSettings::default();
+        let table = value
+            .as_table()
+            .ok_or_else(|| anyhow!("Invalid TOML format"))?;
+
+        for (name, value) in table.iter().filter(|(_, v)| !v.is_table()) {
+            match name.as_str() {
+                "unmapped" => {
+                    let unmapped = value
+                        .as_str()
+                        .ok_or_else(|| anyhow!("Expected string for unmapped, got: {:?}", value))?;
+                    settings.unmapped = unmapped
+                        .parse()
+                        .map_err(|_| anyhow!("Invalid value for unmapped: {}", unmapped))?;
+                }
+                "priority" => {
+                    let priority = value.as_integer().ok_or_else(|| {
+                        anyhow!("Expected integer for priority, got: {:?}", value)
+                    })?;
+                    settings.priority = i32::try_from(priority)
+                        .map_err(|_| anyhow!("Priority out of range: {}", priority))?;
+                }
+                "timeout" => settings.timeout = Some(parse_milliseconds(name, value)?),
+                "notification" => {
+                    settings.notification = Some(match value {
+                        Value::Boolean(true) => Notification::LayerName,
+                        Value::Boolean(false) => Notification::Hidden,
+                        Value::String(label) => Notification::Label(label.clone()),
+                        _ => {
+                            return Err(anyhow!(
+                                "Expected boolean or string for notification, got: {:?}",
+                                value
+                            ))
+                        }
+                    });
+                }
+                "pass_modifiers" => {
+                    settings.pass_modifiers = value.as_bool().ok_or_else(|| {
+                        anyhow!("Expected boolean for pass_modifiers, got: {:?}", value)
+                    })?;
+                }
+                _ => return Err(anyhow!("Unknown layer setting: {}", name)),
+            }
+        }
+
+        Ok(settings)
+    }
 }
 
 impl Action {
+    /// Keys which identify the action of a binding.
+    const KEYS: [&'static str; 15] = [
+        "transparent",
+        "command",
+        "remap",
+        "move_layer",
+        "type",
+        "open_app",
+        "open_url",
+        "applescript",
+        "shortcut",
+        "select_input_source",
+        "mouse",
+        "tap",
+        "hold",
+        "double_tap",
+        "sticky",
+    ];
+
+    /// Tables without an action are not bindings but define a sublayer.
+    fn is_sublayer(value: &Value) -> bool {
+        value.is_table() && !Self::KEYS.iter().any(|k| value.get(k).is_some())
+    }
+
     fn from_toml(value: &Value) -> Result<Self> {
-        if let Some(command) = value.get("command").and_then(|v| v.as_str()) {
+        let mut action = Self::single_from_toml(value)?;
+        if let Some(hold) = value.get("hold").filter(|v| v.is_table()) {
+            action = Action::LongPress(LongPress::from_toml(action, hold, value)?);
+        }
+        match value.get("double_tap") {
+            Some(double_tap) => Ok(Action::DoubleTap(DoubleTap::from_toml(
+                action, double_tap, value,
+            )?)),
+            None => Ok(action),
+        }
+    }
+
+    fn single_from_toml(value: &Value) -> Result<Self> {
+        if let Some(transparent) = value.get("transparent").and_then(|v| v.as_bool()) {
+            if !transparent {
+                return Err(anyhow!("Transparent bindings can only be enabled"));
+            }
+            Ok(Action::Transparent)
+        } else if let Some(command) = value.get("command").and_then(|v| v.as_str()) {
             Ok(Action::Command(Command {
                 value: command.to_string(),
             }))
         } else if let Some(remap) = value.get("remap").and_then(|v| v.as_str()) {
             let keys = Self::parse_keys(remap)?;
-            Ok(Action::LayerRemap(LayerRemap { to: keys }))
+            Ok(Action::LayerRemap(LayerRemap {
+                to: keys,
+                options: KeyOptions::from_toml(value)?,
+            }))
+        } else if let Some(steps) = value.get("remap").and_then(|v| v.as_array()) {
+            let steps = steps
+                .iter()
+                .map(MacroStep::from_toml)
+                .collect::<Result<Vec<MacroStep>>>()?;
+            Ok(Action::Macro(Macro { steps }))
+        } else if let Some(text) = value.get("type").and_then(|v| v.as_str()) {
+            Ok(Action::TypeText(TypeText {
+                text: text.to_string(),
+            }))
+        } else if let Some(open_app) = value.get("open_app") {
+            Ok(Action::OpenApp(OpenApp::from_toml(open_app)?))
+        } else if let Some(url) = value.get("open_url").and_then(|v| v.as_str()) {
+            Ok(Action::OpenUrl(OpenUrl::parse(url)?))
+        } else if let Some(script) = value.get("applescript") {
+            Ok(Action::AppleScript(AppleScript::from_toml(script)?))
+        } else if let Some(shortcut) = value.get("shortcut").and_then(|v| v.as_str()) {
+            Ok(Action::Shortcut(Shortcut {
+                name: shortcut.to_string(),
+            }))
+        } else if let Some(input_source) = value.get("select_input_source") {
+            Ok(Action::SelectInputSource(SelectInputSource::from_toml(
+                input_source,
+            )?))
+        } else if let Some(mouse) = value.get("mouse") {
+            Ok(Action::Mouse(Mouse::from_toml(mouse)?))
+        } else if let Some(sticky) = value.get("sticky") {
+            Ok(Action::Sticky(Sticky::from_toml(sticky)?))
+        } else if value.get("tap").is_some() || value.get("hold").is_some_and(|v| v.is_str()) {
+            Ok(Action::TapHold(TapHold::from_toml(value)?))
         } else if let Some(move_layer) = value.get("move_layer").and_then(|v| v.as_str()) {
             Ok(Action::LayerShift(LayerShift {
                 move_layer: move_layer.to_string(),
@@ -174,48 +755,492 @@ impl Action {
             Err(anyhow!("Unknown action type in TOML"))
         }
     }
-    fn parse_keys(remap: &str) -> Result<Vec<Key>> {
-        remap
-            .split('+')
-            .map(|s| s.parse::<Key>().map_err(|_| anyhow!("Invalid key: {}", s)))
-            .collect()
+    fn parse_keys(remap: &str) -> Result<Vec<KeyCode>> {
+        keys::parse_keys(remap).map_err(|_| anyhow!("Invalid keys: {}", remap))
+    }
+}
+
+impl OpenApp {
+    fn from_toml(value: &Value) -> Result<Self> {
+        if let Some(name) = value.as_str() {
+            Ok(OpenApp::Name(name.to_string()))
+        } else if let Some(bundle_id) = value.get("bundle_id").and_then(|v| v.as_str()) {
+            Ok(OpenApp::BundleId(bundle_id.to_string()))
+        } else if let Some(file_path) = value.get("file_path").and_then(|v| v.as_str()) {
+            Ok(OpenApp::FilePath(file_path.to_string()))
+        } else {
+            Err(anyhow!(
+                "Expected application name, bundle_id or file_path for open_app, got: {:?}",
+                value
+            ))
+        }
+    }
+}
+
+impl OpenUrl {
+    /// Checks that the URL consists of a scheme and a non-empty remainder without whitespace.
+    fn parse(url: &str) -> Result<Self> {
+        let (scheme, remainder) = url
+            .split_once(':')
+            .ok_or_else(|| anyhow!("Missing scheme in URL: {}", url))?;
+
+        let mut scheme_chars = scheme.chars();
+        let valid_scheme = scheme_chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+            && scheme_chars.all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c));
+        if !valid_scheme {
+            return Err(anyhow!("Invalid scheme in URL: {}", url));
+        }
+        let remainder = remainder.trim_start_matches('/');
+        if remainder.is_empty() || url.chars().any(|c| c.is_whitespace() || c.is_control()) {
+            return Err(anyhow!("Invalid URL: {}", url));
+        }
+
+        Ok(OpenUrl {
+            url: url.to_string(),
+        })
+    }
+}
+
+impl AppleScript {
+    fn from_toml(value: &Value) -> Result<Self> {
+        if let Some(script) = value.as_str() {
+            Ok(AppleScript::Inline(script.to_string()))
+        } else if let Some(file) = value.get("file").and_then(|v| v.as_str()) {
+            Ok(AppleScript::File(file.to_string()))
+        } else {
+            Err(anyhow!(
+                "Expected script or file for applescript, got: {:?}",
+                value
+            ))
+        }
+    }
+}
+
+impl DoubleTap {
+    const DEFAULT_TIMEOUT: u32 = 300;
+
+    fn from_toml(single: Action, double: &Value, binding: &Value) -> Result<Self> {
+        if single == Action::Transparent {
+            return Err(anyhow!(
+                "Transparent bindings cannot have a double tap action"
+            ));
+        }
+        check_keys(
+            double,
+            &[&Action::KEYS, &KeyOptions::KEYS],
+            "double_tap action",
+        )?;
+        let double = Action::single_from_toml(double).context("Invalid double_tap action")?;
+        let timeout = binding
+            .get("double_tap_timeout")
+            .map(|v| parse_milliseconds("double_tap_timeout", v))
+            .transpose()?
+            .unwrap_or(Self::DEFAULT_TIMEOUT);
+
+        Ok(DoubleTap {
+            single: Box::new(single),
+            double: Box::new(double),
+            timeout,
+        })
+    }
+}
+
+impl Sticky {
+    /// Either the modifier or a table with the modifier and the mode, which defaults to toggle.
+    fn from_toml(value: &Value) -> Result<Self> {
+        let (modifier, mode) = match value {
+            Value::String(modifier) => (modifier.as_str(), None),
+            Value::Table(table) => (
+                table
+                    .get("modifier")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow!("Missing modifier for sticky: {:?}", value))?,
+                table.get("mode"),
+            ),
+            _ => {
+                return Err(anyhow!(
+                    "Expected string or table for sticky, got: {:?}",
+                    value
+                ))
+            }
+        };
+
+        let modifier = modifier
+            .parse::<Key>()
+            .ok()
+            .filter(|k| k.is_modifier() && !k.is_modifier_only())
+            .ok_or_else(|| anyhow!("Invalid sticky modifier: {}", modifier))?;
+        let mode = mode
+            .map(|v| {
+                v.as_str()
+                    .and_then(|mode| mode.parse().ok())
+                    .ok_or_else(|| anyhow!("Invalid sticky mode: {:?}", v))
+            })
+            .transpose()?
+            .unwrap_or_default();
+
+        Ok(Sticky { modifier, mode })
+    }
+}
+
+impl KeyOptions {
+    const KEYS: [&'static str; 4] = ["repeat", "lazy", "halt", "hold_down_milliseconds"];
+
+    fn from_toml(value: &Value) -> Result<Self> {
+        let flag = |name: &str| -> Result<Option<bool>> {
+            value
+                .get(name)
+                .map(|v| {
+                    v.as_bool()
+                        .ok_or_else(|| anyhow!("Expected boolean for {}, got: {:?}", name, v))
+                })
+                .transpose()
+        };
+
+        Ok(KeyOptions {
+            repeat: flag("repeat")?,
+            lazy: flag("lazy")?,
+            halt: flag("halt")?,
+            hold_down_milliseconds: value
+                .get("hold_down_milliseconds")
+                .map(|v| parse_milliseconds("hold_down_milliseconds", v))
+                .transpose()?,
+        })
+    }
+}
+
+impl LongPress {
+    /// Karabiner's default for `basic.to_if_held_down_threshold_milliseconds`.
+    const DEFAULT_THRESHOLD: u32 = 500;
+
+    fn from_toml(tap: Action, hold: &Value, binding: &Value) -> Result<Self> {
+        if tap == Action::Transparent {
+            return Err(anyhow!("Transparent bindings cannot have a hold action"));
+        }
+        check_keys(hold, &[&Action::KEYS, &KeyOptions::KEYS], "hold action")?;
+        let hold = match Action::single_from_toml(hold).context("Invalid hold action")? {
+            Action::TapHold(_) => return Err(anyhow!("Hold actions cannot be tap/hold actions")),
+            _ if hold.get("hold").is_some() || hold.get("double_tap").is_some() => {
+                return Err(anyhow!(
+                    "Hold actions cannot have a hold or double tap action themselves"
+                ))
+            }
+            hold => hold,
+        };
+        let threshold = binding
+            .get("hold_threshold")
+            .map(|v| parse_milliseconds("hold_threshold", v))
+            .transpose()?
+            .unwrap_or(Self::DEFAULT_THRESHOLD);
+
+        Ok(LongPress {
+            tap: Box::new(tap),
+            hold: Box::new(hold),
+            threshold,
+        })
+    }
+}
+
+impl TapHold {
+    /// Karabiner's default for `basic.to_if_held_down_threshold_milliseconds`, which is also used
+    /// for `basic.to_if_alone_timeout_milliseconds` so that a key is never both tapped and held.
+    const DEFAULT_TIMEOUT: u32 = 500;
+
+    /// Keys of a tap/hold table besides the key options and the options of the binding.
+    const KEYS: [&'static str; 3] = ["tap", "hold", "tap_timeout"];
+
+    fn from_toml(value: &Value) -> Result<Self> {
+        check_keys(
+            value,
+            &[&Self::KEYS, &KeyOptions::KEYS, &LayerAssignment::KEYS],
+            "tap/hold binding",
+        )?;
+
+        let keys = |name: &str| -> Result<Vec<KeyCode>> {
+            let keys = value
+                .get(name)
+                .ok_or_else(|| anyhow!("Missing {} in tap/hold binding: {:?}", name, value))?;
+            let keys = keys
+                .as_str()
+                .ok_or_else(|| anyhow!("Expected string for {}, got: {:?}", name, keys))?;
+            Action::parse_keys(keys)
+        };
+        let timeout = value
+            .get("tap_timeout")
+            .map(|v| parse_milliseconds("tap_timeout", v))
+            .transpose()?
+            .unwrap_or(Self::DEFAULT_TIMEOUT);
+
+        Ok(TapHold {
+            tap: keys("tap")?,
+            hold: keys("hold")?,
+            timeout,
+            options: KeyOptions::from_toml(value)?,
+        })
+    }
+}
+
+impl SelectInputSource {
+    fn from_toml(value: &Value) -> Result<Self> {
+        let field = |name: &str| -> Result<Option<String>> {
+            value
+                .get(name)
+                .map(|v| {
+                    v.as_str()
+                        .map(String::from)
+                        .ok_or_else(|| anyhow!("Expected string for {}, got: {:?}", name, v))
+                })
+                .transpose()
+        };
+        let input_source = SelectInputSource {
+            language: field("language")?,
+            input_source_id: field("input_source_id")?,
+        };
+
+        if input_source.language.is_none() && input_source.input_source_id.is_none() {
+            return Err(anyhow!(
+                "Expected language or input_source_id for select_input_source, got: {:?}",
+                value
+            ));
+        }
+        Ok(input_source)
+    }
+}
+
+impl Mouse {
+    const DEFAULT_MOVE_SPEED: u32 = 1536;
+    const DEFAULT_SCROLL_SPEED: u32 = 32;
+
+    fn from_toml(value: &Value) -> Result<Self> {
+        let table = value
+            .as_table()
+            .ok_or_else(|| anyhow!("Expected table for mouse, got: {:?}", value))?;
+        let speed = |default: u32| -> Result<u32> {
+            table
+                .get("speed")
+                .map(|v| parse_milliseconds("mouse speed", v))
+                .unwrap_or(Ok(default))
+        };
+
+        if let Some(direction) = table.get("move") {
+            Ok(Mouse::Move {
+                direction: Direction::from_toml(direction)?,
+                speed: speed(Self::DEFAULT_MOVE_SPEED)?,
+            })
+        } else if let Some(direction) = table.get("scroll") {
+            Ok(Mouse::Scroll {
+                direction: Direction::from_toml(direction)?,
+                speed: speed(Self::DEFAULT_SCROLL_SPEED)?,
+            })
+        } else if let Some(button) = table.get("click").and_then(|v| v.as_str()) {
+            Ok(Mouse::Click(Self::parse_button(button)?))
+        } else if let Some(multiplier) = table.get("speed_multiplier") {
+            let multiplier = multiplier
+                .as_float()
+                .or_else(|| multiplier.as_integer().map(|i| i as f64))
+                .filter(|m| *m > 0.0)
+                .and_then(serde_json::Number::from_f64)
+                .ok_or_else(|| anyhow!("Invalid speed_multiplier: {:?}", multiplier))?;
+            Ok(Mouse::SpeedMultiplier(multiplier))
+        } else {
+            Err(anyhow!(
+                "Expected move, scroll, click or speed_multiplier for mouse, got: {:?}",
+                value
+            ))
+        }
+    }
+
+    /// Accepts `left`, `right` and `middle` besides karabiner's `button1` to `button32`.
+    fn parse_button(button: &str) -> Result<String> {
+        let number = match button {
+            "left" => 1,
+            "right" => 2,
+            "middle" => 3,
+            _ => button
+                .strip_prefix("button")
+                .and_then(|n| n.parse::<u8>().ok())
+                .filter(|n| (1..=32).contains(n))
+                .ok_or_else(|| anyhow!("Invalid mouse button: {}", button))?,
+        };
+        Ok(format!("button{}", number))
+    }
+}
+
+impl Direction {
+    fn from_toml(value: &Value) -> Result<Self> {
+        let direction = value
+            .as_str()
+            .ok_or_else(|| anyhow!("Expected string for direction, got: {:?}", value))?;
+        direction
+            .parse()
+            .map_err(|_| anyhow!("Invalid direction: {}", direction))
+    }
+}
+
+impl MacroStep {
+    fn from_toml(value: &Value) -> Result<Self> {
+        match value {
+            Value::String(keys) => Ok(MacroStep {
+                keys: Action::parse_keys(keys)?,
+                hold_down_milliseconds: None,
+            }),
+            Value::Table(step) => {
+                let keys = step
+                    .get("keys")
+                    .and_then(|v| v.as_str())
+                    .ok_or_else(|| anyhow!("Missing keys in macro step: {:?}", step))?;
+                let hold_down_milliseconds = step
+                    .get("hold_down_milliseconds")
+                    .map(|v| parse_milliseconds("hold_down_milliseconds", v))
+                    .transpose()?;
+                Ok(MacroStep {
+                    keys: Action::parse_keys(keys)?,
+                    hold_down_milliseconds,
+                })
+            }
+            _ => Err(anyhow!(
+                "Expected string or table for macro step, got: {:?}",
+                value
+            )),
+        }
     }
 }
 
 impl LayerAssignment {
+    /// Keys of a binding table which apply to the binding as a whole instead of its action.
+    const KEYS: [&'static str; 8] = [
+        "next_layer",
+        "description",
+        "pass_modifiers",
+        "key_down_order",
+        "simultaneous_threshold",
+        "hold_threshold",
+        "double_tap",
+        "double_tap_timeout",
+    ];
+
     pub fn from_toml(value: &Value, layer: Layer) -> Result<Vec<Self>> {
         let mut assignments = Vec::new();
         let table = value
             .as_table()
             .ok_or_else(|| anyhow!("Invalid TOML format"))?;
 
-        for (key_str, value) in table {
+        for (key_str, value) in table.iter().filter(|(_, v)| v.is_table()) {
             let key = key_str
-                .parse::<Key>()
+                .parse::<KeyCombo>()
                 .map_err(|_| anyhow!("Invalid key: {}", key_str))?;
 
+            if Action::is_sublayer(value) {
+                assignments.push(LayerAssignment {
+                    layer: layer.clone(),
+                    key,
+                    action: Action::LayerShift(LayerShift {
+                        move_layer: layer.sublayer_name(key_str),
+                    }),
+                    next_layer: None,
+                    description: None,
+                    pass_modifiers: false,
+                });
+                continue;
+            }
+
+            check_keys(
+                value,
+                &[
+                    &Action::KEYS,
+                    &KeyOptions::KEYS,
+                    &TapHold::KEYS,
+                    &LayerAssignment::KEYS,
+                ],
+                &format!("binding of {}", key_str),
+            )?;
             let action = Action::from_toml(value)?;
+            let key = Self::with_simultaneous_options(key, value)
+                .with_context(|| format!("Invalid binding of {}", key_str))?;
 
+            // Bindings of sublayers return to the base layer unless told otherwise.
             let next_layer = value
                 .get("next_layer")
                 .and_then(|v| v.as_str())
-                .map(String::from);
+                .map(String::from)
+                .or_else(|| match (&action, layer.is_sublayer()) {
+                    (Action::LayerShift(_), _) | (_, false) => None,
+                    (_, true) => Some(BASE_LAYER.to_string()),
+                });
 
             let description = value
                 .get("description")
                 .and_then(|v| v.as_str())
                 .map(String::from);
 
+            let pass_modifiers = value
+                .get("pass_modifiers")
+                .map(|v| {
+                    v.as_bool()
+                        .ok_or_else(|| anyhow!("Expected boolean for pass_modifiers, got: {:?}", v))
+                })
+                .transpose()?
+                .unwrap_or(layer.settings.pass_modifiers);
+
             assignments.push(LayerAssignment {
                 layer: layer.clone(),
                 key,
                 action,
                 next_layer,
                 description,
+                pass_modifiers,
             });
         }
 
-        return Ok(assignments);
+        Ok(assignments)
+    }
+
+    fn with_simultaneous_options(mut key: KeyCombo, value: &Value) -> Result<KeyCombo> {
+        let key_down_order = value.get("key_down_order");
+        let threshold = value.get("simultaneous_threshold");
+        if !key.is_simultaneous() && (key_down_order.is_some() || threshold.is_some()) {
+            return Err(anyhow!("Simultaneous options require several keys"));
+        }
+
+        key.key_down_order = key_down_order
+            .map(|v| {
+                v.as_str()
+                    .and_then(|order| order.parse().ok())
+                    .ok_or_else(|| anyhow!("Invalid key_down_order: {:?}", v))
+            })
+            .transpose()?;
+        key.threshold = threshold
+            .map(|v| parse_milliseconds("simultaneous_threshold", v))
+            .transpose()?;
+        Ok(key)
+    }
+
+    /// Tap/hold keys in the simple remaps cannot be expressed as simple modifications and become
+    /// bindings of the base layer instead.
+    fn from_simple_remaps(value: &Value, base_layer: &Layer) -> Result<Vec<Self>> {
+        let table = value.as_table().context("Invalid remaps format")?;
+
+        table
+            .iter()
+            .filter(|(_, v)| v.is_table() && v.get("remap").is_none())
+            .map(|(key_str, value)| {
+                let key = key_str
+                    .parse::<KeyCombo>()
+                    .map_err(|_| anyhow!("Invalid key in remaps: {}", key_str))?;
+                let action = Action::from_toml(value)?;
+                if !matches!(action, Action::TapHold(_)) {
+                    return Err(anyhow!("Expected tap/hold remap for {}", key_str));
+                }
+                Ok(LayerAssignment {
+                    layer: base_layer.clone(),
+                    key,
+                    action,
+                    next_layer: None,
+                    description: None,
+                    pass_modifiers: false,
+                })
+            })
+            .collect()
     }
 }
 
@@ -231,11 +1256,18 @@ impl LayerAssignments {
     }
 
     fn get_assignments_for_layer(name: &str, value: &Value) -> Result<Value> {
+        let mut path = name.split(SUBLAYER_SEPARATOR);
+        let name = path.next().unwrap_or_default();
         let table = value
             .as_table()
             .ok_or_else(|| anyhow!("Expected a table, but found something else"))?;
         if let Some((_, matched_value)) = table.iter().find(|(key, _)| key.starts_with(name)) {
-            return Ok(matched_value.clone());
+            return path.try_fold(matched_value.clone(), |value, key| {
+                value
+                    .get(key)
+                    .cloned()
+                    .ok_or_else(|| anyhow!("Sublayer not found: {}", key))
+            });
         }
         Err(anyhow!("Layer not found: {}", name))
     }
@@ -244,6 +1276,7 @@ impl LayerAssignments {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::keys::{ConsumerKey, KeyDownOrder};
     use pretty_assertions::assert_eq;
 
     #[test]
@@ -271,17 +1304,19 @@ mod tests {
 
         let layer = Layer {
             name: "layer1".to_string(),
-            keys: vec![Key::LeftCommand],
+            keys: Key::LeftCommand.into(),
+            settings: LayerSettings::default(),
         };
 
         let expected = vec![LayerAssignment {
             layer: layer.clone(),
-            key: Key::H,
+            key: Key::H.into(),
             action: Action::Command(Command {
                 value: String::from("hello"),
             }),
             next_layer: None,
             description: None,
+            pass_modifiers: false,
         }];
         let toml_value: Value = toml_str.parse()?;
         let layer_assignment = LayerAssignment::from_toml(&toml_value, layer)?;
@@ -298,17 +1333,20 @@ mod tests {
 
         let layer = Layer {
             name: "layer1".to_string(),
-            keys: vec![Key::LeftControl],
+            keys: Key::LeftControl.into(),
+            settings: LayerSettings::default(),
         };
 
         let expected = vec![LayerAssignment {
             layer: layer.clone(),
-            key: Key::H,
+            key: Key::H.into(),
             action: Action::LayerRemap(LayerRemap {
-                to: vec![Key::J, Key::V],
+                to: vec![Key::J.into(), Key::V.into()],
+                options: KeyOptions::default(),
             }),
             next_layer: None,
             description: None,
+            pass_modifiers: false,
         }];
         let toml_value: Value = toml_str.parse()?;
         let layer_assignment = LayerAssignment::from_toml(&toml_value, layer)?;
@@ -319,15 +1357,558 @@ mod tests {
     }
 
     #[test]
-    fn test_get_assignments_for_layer() -> anyhow::Result<()> {
+    fn test_parse_simultaneous_binding() -> anyhow::Result<()> {
         let toml_str = r#"
-            [layer1]
-            h = { command = "hello" }
-            y = { command = "hello2", target_layer = "baselayer", description = "These arguments are optional" }
-            a = { remap =  "left_command+shift+left_arrow"}
-
-            [layer2]
-            a = { command = "app_launcher" }
+            "j&k" = { remap = "escape", key_down_order = "strict", simultaneous_threshold = 80 }
+            "left_command+l" = { remap = "right_arrow" }
+            "#;
+        let layer = Layer {
+            name: BASE_LAYER.to_string(),
+            keys: KeyCombo::default(),
+            settings: LayerSettings::default(),
+        };
+
+        let toml_value: Value = toml_str.parse()?;
+        let assignments = LayerAssignment::from_toml(&toml_value, layer.clone())?;
+
+        assert_eq!(
+            assignments[0].key,
+            KeyCombo {
+                keys: vec![Key::J.into(), Key::K.into()],
+                modifiers: vec![],
+                key_down_order: Some(KeyDownOrder::Strict),
+                threshold: Some(80),
+            }
+        );
+        assert_eq!(
+            assignments[1].key,
+            KeyCombo {
+                keys: vec![Key::L.into()],
+                modifiers: vec![Key::LeftCommand],
+                ..Default::default()
+            }
+        );
+
+        let single_key: Value = r#"j = { remap = "escape", key_down_order = "strict" }"#.parse()?;
+        assert!(LayerAssignment::from_toml(&single_key, layer.clone()).is_err());
+
+        let plus_combo: Value = r#""j+k" = { remap = "escape" }"#.parse()?;
+        assert!(LayerAssignment::from_toml(&plus_combo, layer).is_err());
+
+        let layers = Layers::from_toml(&r#"nav = "left_command+j&k""#.parse()?)?;
+        assert_eq!(
+            layers.layers[0].keys,
+            KeyCombo {
+                keys: vec![Key::J.into(), Key::K.into()],
+                modifiers: vec![Key::LeftCommand],
+                ..Default::default()
+            }
+        );
+        assert!(Layers::from_toml(&r#"nav = "j+k""#.parse()?).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_layer_settings() -> anyhow::Result<()> {
+        let toml_str = r#"
+            unmapped = "exit_and_passthrough"
+            h = { command = "hello" }
+            "#;
+
+        let toml_value: Value = toml_str.parse()?;
+        let settings = LayerSettings::from_toml(&toml_value)?;
+
+        assert_eq!(settings.unmapped, UnmappedKeys::ExitAndPassthrough);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_layer_notification() -> anyhow::Result<()> {
+        let parse = |toml_str: &str| -> anyhow::Result<Option<Notification>> {
+            Ok(LayerSettings::from_toml(&toml_str.parse()?)?.notification)
+        };
+
+        assert_eq!(parse("")?, None);
+        assert_eq!(parse("notification = true")?, Some(Notification::LayerName));
+        assert_eq!(parse("notification = false")?, Some(Notification::Hidden));
+        assert_eq!(
+            parse(r#"notification = "NAV""#)?,
+            Some(Notification::Label("NAV".to_string()))
+        );
+        assert!(parse("notification = 1").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_pass_modifiers() -> anyhow::Result<()> {
+        let toml_str = r#"
+            pass_modifiers = true
+            h = { remap = "left_arrow" }
+            j = { remap = "down_arrow", pass_modifiers = false }
+            "#;
+        let toml_value: Value = toml_str.parse()?;
+        let layer = Layer {
+            name: "nav".to_string(),
+            keys: KeyCombo::default(),
+            settings: LayerSettings::from_toml(&toml_value)?,
+        };
+
+        let assignments = LayerAssignment::from_toml(&toml_value, layer)?;
+
+        assert!(assignments[0].pass_modifiers);
+        assert!(!assignments[1].pass_modifiers);
+        assert!(LayerSettings::from_toml(&"pass_modifiers = 1".parse()?).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_modifier_aliases() -> anyhow::Result<()> {
+        assert_eq!(
+            Action::parse_keys("hyper")?,
+            vec![
+                KeyCode::from(Key::LeftCommand),
+                Key::LeftShift.into(),
+                Key::LeftOption.into(),
+                Key::LeftControl.into()
+            ]
+        );
+        assert_eq!(
+            "meh+k".parse::<KeyCombo>()?,
+            KeyCombo {
+                keys: vec![Key::K.into()],
+                modifiers: vec![Key::LeftShift, Key::LeftOption, Key::LeftControl],
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            "command+shift+h".parse::<KeyCombo>()?,
+            KeyCombo {
+                keys: vec![Key::H.into()],
+                modifiers: vec![Key::Command, Key::Shift],
+                ..Default::default()
+            }
+        );
+        assert!(Action::parse_keys("command").is_err());
+        assert!(Action::parse_keys("option+shift").is_err());
+        assert!(SimpleRemaps::from_toml(&r#"caps_lock = "control""#.parse()?).is_err());
+
+        let layers = Layers::from_toml(&r#"layer1 = "l+hyper""#.parse()?)?;
+        assert_eq!(
+            layers.layers[0].keys,
+            KeyCombo {
+                keys: vec![Key::L.into()],
+                modifiers: keys::HYPER.to_vec(),
+                ..Default::default()
+            }
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_key_options() -> anyhow::Result<()> {
+        let toml_str = r#"
+            remap = "left_command"
+            repeat = false
+            lazy = true
+            halt = true
+            hold_down_milliseconds = 50
+            "#;
+
+        assert_eq!(
+            Action::from_toml(&toml_str.parse()?)?,
+            Action::LayerRemap(LayerRemap {
+                to: vec![Key::LeftCommand.into()],
+                options: KeyOptions {
+                    repeat: Some(false),
+                    lazy: Some(true),
+                    halt: Some(true),
+                    hold_down_milliseconds: Some(50),
+                },
+            })
+        );
+        assert!(Action::from_toml(
+            &r#"remap = "a"
+repeat = "no""#
+                .parse()?
+        )
+        .is_err());
+
+        let remaps = SimpleRemaps::from_toml(
+            &r#"
+            caps_lock = { remap = "escape", repeat = false }
+            right_command = "left_command"
+            "#
+            .parse()?,
+        )?;
+        assert_eq!(remaps.remaps.len(), 2);
+        assert_eq!(remaps.remaps[0].options.repeat, Some(false));
+        assert_eq!(remaps.remaps[1].options, KeyOptions::default());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_sticky() -> anyhow::Result<()> {
+        let parse =
+            |toml_str: &str| -> anyhow::Result<Action> { Action::from_toml(&toml_str.parse()?) };
+
+        assert_eq!(
+            parse(r#"sticky = "left_shift""#)?,
+            Action::Sticky(Sticky {
+                modifier: Key::LeftShift,
+                mode: StickyModifierMode::Toggle,
+            })
+        );
+        assert_eq!(
+            parse(r#"sticky = { modifier = "right_command", mode = "off" }"#)?,
+            Action::Sticky(Sticky {
+                modifier: Key::RightCommand,
+                mode: StickyModifierMode::Off,
+            })
+        );
+        assert!(parse(r#"sticky = "shift""#).is_err());
+        assert!(parse(r#"sticky = "a""#).is_err());
+        assert!(parse(r#"sticky = { modifier = "left_shift", mode = "hold" }"#).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_transparent_action() -> anyhow::Result<()> {
+        let toml_value: Value = "transparent = true".parse()?;
+
+        assert_eq!(Action::from_toml(&toml_value)?, Action::Transparent);
+        Ok(())
+    }
+
+    #[test]
+    fn test_layers_by_priority() {
+        let layer = |name: &str, priority: i32| Layer {
+            name: name.to_string(),
+            keys: KeyCombo::default(),
+            settings: LayerSettings {
+                priority,
+                ..Default::default()
+            },
+        };
+        let layers = Layers {
+            layers: vec![
+                layer(BASE_LAYER, 10),
+                layer("layer3", 0),
+                layer("layer2", 5),
+                layer("layer1", 0),
+            ],
+        };
+
+        let names: Vec<&str> = layers
+            .by_priority()
+            .iter()
+            .map(|l| l.name.as_str())
+            .collect();
+
+        assert_eq!(names, vec!["layer2", "layer1", "layer3", BASE_LAYER]);
+    }
+
+    #[test]
+    fn test_parse_settings() -> anyhow::Result<()> {
+        let toml_str = r#"
+            exclusive_layers = true
+            reset = "escape+left_control"
+
+            [layers]
+            layer1 = "left_command"
+            "#;
+
+        let toml_value: Value = toml_str.parse()?;
+        let settings = Settings::from_toml(&toml_value)?;
+
+        assert!(settings.exclusive_layers);
+        assert_eq!(settings.reset.unwrap(), vec![Key::Escape, Key::LeftControl]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_unknown_layer_setting_fails() -> anyhow::Result<()> {
+        let toml_str = r#"
+            unknown = "value"
+            "#;
+
+        let toml_value: Value = toml_str.parse()?;
+
+        assert!(LayerSettings::from_toml(&toml_value).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_modifier_only_keys_fail() -> anyhow::Result<()> {
+        let layer = Layer {
+            name: "layer1".to_string(),
+            keys: Key::LeftCommand.into(),
+            settings: LayerSettings::default(),
+        };
+
+        let binding: Value = r#"any = { remap = "a" }"#.parse()?;
+        assert!(LayerAssignment::from_toml(&binding, layer.clone()).is_err());
+        let remap: Value = r#"a = { remap = "b+any" }"#.parse()?;
+        assert!(LayerAssignment::from_toml(&remap, layer.clone()).is_err());
+        let remap: Value = r#"b = { remap = "a+any" }"#.parse()?;
+        assert!(LayerAssignment::from_toml(&remap, layer).is_err());
+        assert!(Action::parse_keys("a+any").is_err());
+        assert!(SimpleRemaps::from_toml(&r#"caps_lock = "any""#.parse()?).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_macro() -> anyhow::Result<()> {
+        let toml_str = r#"
+            remap = ["left_command+k", { keys = "left_command+s", hold_down_milliseconds = 50 }]
+            "#;
+
+        let expected = Action::Macro(Macro {
+            steps: vec![
+                MacroStep {
+                    keys: vec![Key::LeftCommand.into(), Key::K.into()],
+                    hold_down_milliseconds: None,
+                },
+                MacroStep {
+                    keys: vec![Key::LeftCommand.into(), Key::S.into()],
+                    hold_down_milliseconds: Some(50),
+                },
+            ],
+        });
+        let toml_value: Value = toml_str.parse()?;
+        let action = Action::from_toml(&toml_value)?;
+
+        assert_eq!(action, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_type_text() -> anyhow::Result<()> {
+        let toml_str = r#"
+            type = "git status -sb\n"
+            "#;
+
+        let expected = Action::TypeText(TypeText {
+            text: String::from("git status -sb\n"),
+        });
+        let toml_value: Value = toml_str.parse()?;
+
+        assert_eq!(Action::from_toml(&toml_value)?, expected);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_open_app() -> anyhow::Result<()> {
+        let toml_value: Value = r#"open_app = "Slack""#.parse()?;
+        assert_eq!(
+            Action::from_toml(&toml_value)?,
+            Action::OpenApp(OpenApp::Name(String::from("Slack")))
+        );
+
+        let toml_value: Value =
+            r#"open_app = { bundle_id = "com.tinyspeck.slackmacgap" }"#.parse()?;
+        assert_eq!(
+            Action::from_toml(&toml_value)?,
+            Action::OpenApp(OpenApp::BundleId(String::from("com.tinyspeck.slackmacgap")))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_open_url() -> anyhow::Result<()> {
+        let toml_value: Value =
+            r#"open_url = "raycast://extensions/raycast/clipboard-history""#.parse()?;
+        assert_eq!(
+            Action::from_toml(&toml_value)?,
+            Action::OpenUrl(OpenUrl {
+                url: String::from("raycast://extensions/raycast/clipboard-history")
+            })
+        );
+
+        for invalid in [
+            "example.com",
+            "https://",
+            "1http://example.com",
+            "https://a b",
+        ] {
+            let toml_value: Value = format!("open_url = {:?}", invalid).parse()?;
+            assert!(Action::from_toml(&toml_value).is_err(), "{}", invalid);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_applescript_and_shortcut() -> anyhow::Result<()> {
+        let toml_str = r#"
+            a = { applescript = """
+            tell application "Music"
+                playpause
+            end tell""" }
+            f = { applescript = { file = "~/scripts/focus.scpt" } }
+            s = { shortcut = "Start Focus" }
+            "#;
+
+        let toml_value: Value = toml_str.parse()?;
+        let action = |key: &str| Action::from_toml(toml_value.get(key).unwrap());
+
+        assert_eq!(
+            action("a")?,
+            Action::AppleScript(AppleScript::Inline(String::from(
+                "            tell application \"Music\"\n                playpause\n            end tell"
+            )))
+        );
+        assert_eq!(
+            action("f")?,
+            Action::AppleScript(AppleScript::File(String::from("~/scripts/focus.scpt")))
+        );
+        assert_eq!(
+            action("s")?,
+            Action::Shortcut(Shortcut {
+                name: String::from("Start Focus")
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_select_input_source() -> anyhow::Result<()> {
+        let parse = |toml_str: &str| -> anyhow::Result<Action> {
+            Action::from_toml(&toml_str.parse::<Value>()?)
+        };
+
+        assert_eq!(
+            parse(r#"select_input_source = { language = "de" }"#)?,
+            Action::SelectInputSource(SelectInputSource {
+                language: Some("de".to_string()),
+                input_source_id: None,
+            })
+        );
+        assert_eq!(
+            parse(r#"select_input_source = { input_source_id = "com.apple.keylayout.US" }"#)?,
+            Action::SelectInputSource(SelectInputSource {
+                language: None,
+                input_source_id: Some("com.apple.keylayout.US".to_string()),
+            })
+        );
+        assert!(parse("select_input_source = {}").is_err());
+        assert!(parse("select_input_source = { language = 1 }").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_double_tap() -> anyhow::Result<()> {
+        let toml_str = r#"
+            remap = "escape"
+            double_tap = { command = "pmset displaysleepnow" }
+            double_tap_timeout = 250
+            "#;
+
+        assert_eq!(
+            Action::from_toml(&toml_str.parse()?)?,
+            Action::DoubleTap(DoubleTap {
+                single: Box::new(Action::LayerRemap(LayerRemap {
+                    to: vec![Key::Escape.into()],
+                    options: KeyOptions::default(),
+                })),
+                double: Box::new(Action::Command(Command {
+                    value: "pmset displaysleepnow".to_string(),
+                })),
+                timeout: 250,
+            })
+        );
+        assert!(Action::from_toml(&r#"double_tap = { remap = "a" }"#.parse()?).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_long_press() -> anyhow::Result<()> {
+        let toml_str = r#"
+            open_app = "Slack"
+            hold = { remap = "k+left_command+left_shift" }
+            hold_threshold = 400
+            next_layer = "baselayer"
+            "#;
+
+        assert_eq!(
+            Action::from_toml(&toml_str.parse()?)?,
+            Action::LongPress(LongPress {
+                tap: Box::new(Action::OpenApp(OpenApp::Name("Slack".to_string()))),
+                hold: Box::new(Action::LayerRemap(LayerRemap {
+                    to: vec![
+                        Key::K.into(),
+                        Key::LeftCommand.into(),
+                        Key::LeftShift.into()
+                    ],
+                    options: KeyOptions::default(),
+                })),
+                threshold: 400,
+            })
+        );
+        assert!(matches!(
+            Action::from_toml(
+                &r#"tap = "a"
+hold = "b""#
+                    .parse()?
+            )?,
+            Action::TapHold(_)
+        ));
+        assert!(Action::from_toml(&r#"hold = { command = "ls" }"#.parse()?).is_err());
+        assert!(Action::from_toml(
+            &r#"s = { remap = "k", hold = { tap = "a", hold = "b" } }"#.parse::<Value>()?["s"]
+        )
+        .is_err());
+        assert!(Action::from_toml(
+            &r#"s = { remap = "k", hold = { remap = "a", double_tap = { remap = "b" } } }"#
+                .parse::<Value>()?["s"]
+        )
+        .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_mouse() -> anyhow::Result<()> {
+        let parse = |toml_str: &str| -> anyhow::Result<Action> {
+            Action::from_toml(&toml_str.parse::<Value>()?)
+        };
+
+        assert_eq!(
+            parse(r#"mouse = { move = "left", speed = 1500 }"#)?,
+            Action::Mouse(Mouse::Move {
+                direction: Direction::Left,
+                speed: 1500
+            })
+        );
+        assert_eq!(
+            parse(r#"mouse = { scroll = "down" }"#)?,
+            Action::Mouse(Mouse::Scroll {
+                direction: Direction::Down,
+                speed: 32
+            })
+        );
+        assert_eq!(
+            parse(r#"mouse = { click = "right" }"#)?,
+            Action::Mouse(Mouse::Click("button2".to_string()))
+        );
+        assert_eq!(
+            parse(r#"mouse = { speed_multiplier = 2.5 }"#)?,
+            Action::Mouse(Mouse::SpeedMultiplier(
+                serde_json::Number::from_f64(2.5).unwrap()
+            ))
+        );
+        assert!(parse(r#"mouse = { move = "sideways" }"#).is_err());
+        assert!(parse(r#"mouse = { click = "button33" }"#).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_get_assignments_for_layer() -> anyhow::Result<()> {
+        let toml_str = r#"
+            [layer1]
+            h = { command = "hello" }
+            y = { command = "hello2", target_layer = "baselayer", description = "These arguments are optional" }
+            a = { remap =  "left_command+shift+left_arrow"}
+
+            [layer2]
+            a = { command = "app_launcher" }
             "#;
         let expected_toml = toml::toml! {
             h = { command = "hello" }
@@ -360,12 +1941,14 @@ mod tests {
 
         let layer1 = Layer {
             name: "layer1".to_string(),
-            keys: vec![Key::LeftCommand],
+            keys: Key::LeftCommand.into(),
+            settings: LayerSettings::default(),
         };
 
         let layer = Layer {
             name: "baselayer".to_string(),
-            keys: vec![Key::CapsLock],
+            keys: Key::CapsLock.into(),
+            settings: LayerSettings::default(),
         };
         let layers = vec![layer.clone(), layer1.clone()];
 
@@ -376,36 +1959,44 @@ mod tests {
             assignments: vec![
                 LayerAssignment {
                     layer: layer.clone(),
-                    key: Key::CapsLock,
+                    key: Key::CapsLock.into(),
                     action: Action::LayerRemap(LayerRemap {
                         to: vec![
-                            Key::LeftCommand,
-                            Key::LeftShift,
-                            Key::LeftOption,
-                            Key::LeftControl,
+                            Key::LeftCommand.into(),
+                            Key::LeftShift.into(),
+                            Key::LeftOption.into(),
+                            Key::LeftControl.into(),
                         ],
+                        options: KeyOptions::default(),
                     }),
                     next_layer: None,
                     description: None,
+                    pass_modifiers: false,
                 },
                 LayerAssignment {
                     layer: layer1.clone(),
-                    key: Key::A,
-                    action: Action::LayerRemap(LayerRemap { to: vec![Key::B] }),
+                    key: Key::A.into(),
+                    action: Action::LayerRemap(LayerRemap {
+                        to: vec![Key::B.into()],
+                        options: KeyOptions::default(),
+                    }),
                     next_layer: Some(layer.name),
                     description: None,
+                    pass_modifiers: false,
                 },
                 LayerAssignment {
                     layer: Layer {
                         name: String::from("layer1"),
-                        keys: vec![Key::LeftCommand],
+                        keys: Key::LeftCommand.into(),
+                        settings: LayerSettings::default(),
                     },
-                    key: Key::Escape,
+                    key: Key::Escape.into(),
                     action: Action::LayerShift(LayerShift {
                         move_layer: String::from("baselayer"),
                     }),
                     next_layer: None,
                     description: None,
+                    pass_modifiers: false,
                 },
             ],
         };
@@ -426,11 +2017,13 @@ mod tests {
 
         let layer1 = Layer {
             name: "layer1".to_string(),
-            keys: vec![Key::LeftCommand],
+            keys: Key::LeftCommand.into(),
+            settings: LayerSettings::default(),
         };
         let layer2 = Layer {
             name: "layer2".to_string(),
-            keys: vec![Key::A],
+            keys: Key::A.into(),
+            settings: LayerSettings::default(),
         };
 
         let layers = vec![layer1.clone(), layer2.clone()];
@@ -442,30 +2035,33 @@ mod tests {
             assignments: vec![
                 LayerAssignment {
                     layer: layer1.clone(),
-                    key: Key::H,
+                    key: Key::H.into(),
                     action: Action::Command(Command {
                         value: String::from("hello"),
                     }),
                     next_layer: None,
                     description: None,
+                    pass_modifiers: false,
                 },
                 LayerAssignment {
                     layer: layer1.clone(),
-                    key: Key::Y,
+                    key: Key::Y.into(),
                     action: Action::Command(Command {
                         value: String::from("hello2"),
                     }),
                     next_layer: Some(layer1.clone().name),
                     description: Some(String::from("These arguments are optional")),
+                    pass_modifiers: false,
                 },
                 LayerAssignment {
                     layer: layer2.clone(),
-                    key: Key::A,
+                    key: Key::A.into(),
                     action: Action::Command(Command {
                         value: String::from("app_launcher"),
                     }),
                     next_layer: None,
                     description: None,
+                    pass_modifiers: false,
                 },
             ],
         };
@@ -492,6 +2088,37 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_consumer_keys_from_toml() -> anyhow::Result<()> {
+        let toml_str = r#"
+        [simple_remaps]
+        f8 = "play_or_pause"
+        vk_consumer_next = "f9"
+        "#;
+
+        let toml_value: Value = toml_str.parse()?;
+        let remaps = SimpleRemaps::from_toml(toml_value.get("simple_remaps").unwrap())?;
+
+        assert_eq!(remaps.remaps[0].from, Key::F8);
+        assert_eq!(
+            remaps.remaps[0].to[0],
+            KeyCode::from(ConsumerKey::PlayOrPause)
+        );
+        assert_eq!(
+            remaps.remaps[1].from,
+            KeyCode::from(ConsumerKey::ScanNextTrack)
+        );
+        assert_eq!(remaps.remaps[1].to[0], Key::F9);
+        Ok(())
+    }
+
+    #[test]
+    fn test_consumer_key_as_modifier_fails() {
+        assert!(Action::parse_keys("play_or_pause+left_shift").is_ok());
+        assert!(Action::parse_keys("a+play_or_pause").is_err());
+        assert!(Action::parse_keys("play_or_pause+mute").is_err());
+    }
+
     #[test]
     fn test_layers_from_toml() -> Result<()> {
         let toml_str = r#"
@@ -505,11 +2132,17 @@ mod tests {
 
         assert_eq!(layers.layers.len(), 2);
         assert_eq!(layers.layers[0].name, "layer1".to_string());
-        assert_eq!(layers.layers[0].keys[0], Key::LeftCommand);
+        assert_eq!(layers.layers[0].keys, Key::LeftCommand);
 
         assert_eq!(layers.layers[1].name, "layer2".to_string());
-        assert_eq!(layers.layers[1].keys[0], Key::LeftCommand);
-        assert_eq!(layers.layers[1].keys[1], Key::V);
+        assert_eq!(
+            layers.layers[1].keys,
+            KeyCombo {
+                keys: vec![Key::V.into()],
+                modifiers: vec![Key::LeftCommand],
+                ..Default::default()
+            }
+        );
         Ok(())
     }
 
@@ -542,4 +2175,215 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_tap_hold_from_simple_remaps() -> Result<()> {
+        let toml_str = r#"
+            [simple_remaps]
+            caps_lock = { tap = "escape", hold = "left_command+left_shift+left_option+left_control", tap_timeout = 200 }
+            right_command = "escape"
+
+            [baselayer]
+
+            [layers]
+            layer1 = "left_command"
+
+            [layer1]
+            "#;
+
+        let toml_value: Value = toml_str.parse()?;
+        let config = Configuration::from_toml(&toml_value)?;
+
+        assert_eq!(config.simple_remaps.remaps.len(), 1);
+        let assignment = config
+            .layer_assignments
+            .assignments
+            .iter()
+            .find(|a| a.key == Key::CapsLock)
+            .unwrap();
+        assert_eq!(assignment.layer.name, BASE_LAYER);
+        assert_eq!(
+            assignment.action,
+            Action::TapHold(TapHold {
+                tap: vec![Key::Escape.into()],
+                hold: vec![
+                    Key::LeftCommand.into(),
+                    Key::LeftShift.into(),
+                    Key::LeftOption.into(),
+                    Key::LeftControl.into(),
+                ],
+                timeout: 200,
+                options: KeyOptions::default(),
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_tap_hold_options() -> Result<()> {
+        let parse = |toml_str: &str| -> Result<Action> { Action::from_toml(&toml_str.parse()?) };
+
+        assert_eq!(
+            parse(
+                r#"tap = "a"
+hold = "b"
+halt = true
+next_layer = "baselayer""#
+            )?,
+            Action::TapHold(TapHold {
+                tap: vec![Key::A.into()],
+                hold: vec![Key::B.into()],
+                timeout: 500,
+                options: KeyOptions {
+                    halt: Some(true),
+                    ..Default::default()
+                },
+            })
+        );
+        assert!(parse(
+            r#"tap = "a"
+hold = "b"
+hlat = true"#
+        )
+        .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_unknown_binding_options_fail() -> Result<()> {
+        let layer = Layer {
+            name: "layer1".to_string(),
+            keys: Key::LeftCommand.into(),
+            settings: LayerSettings::default(),
+        };
+        let parse = |toml_str: &str| -> Result<Vec<LayerAssignment>> {
+            LayerAssignment::from_toml(&toml_str.parse()?, layer.clone())
+        };
+
+        assert!(parse(r#"h = { remap = "escape", repeat = false }"#).is_ok());
+        assert_eq!(
+            parse(r#"h = { remap = "escape", repaet = false }"#)
+                .unwrap_err()
+                .to_string(),
+            "Unknown option for binding of h: repaet"
+        );
+        assert!(
+            parse(r#"s = { command = "ls", hold = { remap = "k" }, hold_treshold = 400 }"#)
+                .is_err()
+        );
+        assert!(
+            parse(r#"s = { command = "ls", hold = { remap = "k", repaet = false } }"#).is_err()
+        );
+        assert!(parse(r#"s = { command = "ls", double_tap = { comand = "pwd" } }"#).is_err());
+        assert!(SimpleRemaps::from_toml(
+            &r#"caps_lock = { remap = "escape", repaet = false }"#.parse()?
+        )
+        .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_simple_remaps_only_accept_tap_hold_tables() -> Result<()> {
+        let toml_str = r#"
+            [simple_remaps]
+            caps_lock = { command = "hello" }
+
+            [baselayer]
+
+            [layers]
+            layer1 = "left_command"
+
+            [layer1]
+            "#;
+
+        let toml_value: Value = toml_str.parse()?;
+
+        assert!(Configuration::from_toml(&toml_value).is_err());
+        assert!(Action::from_toml(&r#"tap = "escape""#.parse()?).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_home_row_mods_from_toml() -> Result<()> {
+        let toml_str = r#"
+            hold_timeout = 180
+            a = "left_control"
+            s = { hold = "left_option", hold_timeout = 250 }
+            "#;
+
+        let home_row_mods = HomeRowMods::from_toml(&toml_str.parse()?)?;
+
+        assert_eq!(home_row_mods.hold_timeout, 180);
+        assert_eq!(home_row_mods.typing_streak_timeout, 150);
+        assert_eq!(
+            home_row_mods.keys,
+            vec![
+                HomeRowMod {
+                    key: Key::A,
+                    modifier: Key::LeftControl,
+                    hold_timeout: None,
+                },
+                HomeRowMod {
+                    key: Key::S,
+                    modifier: Key::LeftOption,
+                    hold_timeout: Some(250),
+                },
+            ]
+        );
+        assert!(HomeRowMods::from_toml(&r#"a = "b""#.parse()?).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_sublayers_from_toml() -> Result<()> {
+        let toml_str = r#"
+            [simple_remaps]
+
+            [baselayer]
+
+            [layers]
+            layer1 = "l+left_command"
+
+            [layer1.w]
+            timeout = 1000
+            h = { remap = "k+left_shift+left_option" }
+
+            [layer1.w.x]
+            a = { command = "hello" }
+            "#;
+
+        let toml_value: Value = toml_str.parse()?;
+        let config = Configuration::from_toml(&toml_value)?;
+
+        let names: Vec<&str> = config
+            .layers
+            .layers
+            .iter()
+            .map(|l| l.name.as_str())
+            .collect();
+        assert_eq!(names, vec![BASE_LAYER, "layer1", "layer1.w", "layer1.w.x"]);
+        assert_eq!(config.layers.layers[2].settings.timeout, Some(1000));
+        assert!(config.layers.layers[2].is_sublayer());
+
+        let assignments = &config.layer_assignments.assignments;
+        let enter = assignments
+            .iter()
+            .find(|a| a.layer.name == "layer1")
+            .unwrap();
+        assert_eq!(enter.key, Key::W);
+        assert_eq!(
+            enter.action,
+            Action::LayerShift(LayerShift {
+                move_layer: "layer1.w".to_string()
+            })
+        );
+
+        let binding = assignments.iter().find(|a| a.key == Key::H).unwrap();
+        assert_eq!(binding.layer.name, "layer1.w");
+        assert_eq!(binding.next_layer, Some(BASE_LAYER.to_string()));
+
+        let nested = assignments.iter().find(|a| a.key == Key::A).unwrap();
+        assert_eq!(nested.layer.name, "layer1.w.x");
+        Ok(())
+    }
 }
@@ -1,26 +1,98 @@
+use std::cmp::Reverse;
+
+use anyhow::{anyhow, Context as _, Result};
+use strum::IntoEnumIterator;
+
 use crate::configuration::*;
 use crate::karabiner::*;
-use crate::keys::Key;
+use crate::keys::{Key, KeyCode, KeyCombo, KeyboardLayout};
 
 pub const DEFAULT_PROFILE_NAME: &str = "Default";
+/// Variable which holds the name of the active layer when the layers are exclusive.
+pub const LAYER_VARIABLE: &str = "karamapper_layer";
+/// Id of the notification which shows the active layer.
+pub const NOTIFICATION_ID: &str = "karamapper_layer_notification";
+/// Variable which is set while characters are typed in quick succession.
+pub const TYPING_STREAK_VARIABLE: &str = "karamapper_typing_streak";
 
-pub fn convert_configuration(configuration: &Configuration) -> KarabinerConfig {
-    let mut layer_rules: Vec<Rule> = configuration
+pub fn convert_configuration(configuration: &Configuration) -> Result<KarabinerConfig> {
+    let layer_names: Vec<String> = configuration
         .layers
         .layers
         .iter()
         .filter(|l| l.name != BASE_LAYER)
-        .map(|l| layer_to_rule(l.clone()))
+        .map(|l| l.name.clone())
         .collect();
 
-    let rules: Vec<Rule> = configuration
-        .layer_assignments
-        .assignments
+    let mut layer_rules: Vec<Rule> = configuration
+        .settings
+        .reset
         .iter()
-        .map(|a| layer_assignment_to_rule(a.clone()))
+        .map(|keys| Rule::reset_layers(layer_names.clone(), keys.clone().into()))
         .collect();
 
-    layer_rules.extend(rules);
+    layer_rules.extend(
+        configuration
+            .layers
+            .layers
+            .iter()
+            .filter(|l| !l.keys.is_empty())
+            .map(|l| layer_to_rule(l.clone())),
+    );
+
+    let layers = configuration.layers.by_priority();
+    let assignments = &configuration.layer_assignments;
+
+    // The rule for unmapped keys of a layer comes right after its bindings, so that it does not
+    // shadow them but wins over the bindings of layers with a lower precedence.
+    for layer in &layers {
+        for a in layer_assignments_by_modifiers(layer, assignments) {
+            let rule = layer_assignment_to_rule(
+                a.clone(),
+                &configuration.settings.keyboard_layout,
+                &layers,
+            )
+            .with_context(|| format!("Invalid binding of {} in {}", a.key, a.layer.name))?;
+            layer_rules.extend(
+                rule.map(|r| r.with_conditions(precedence_conditions(a, &layers, assignments))),
+            );
+        }
+
+        if layer.name != BASE_LAYER && layer.settings.unmapped == UnmappedKeys::ExitAndPassthrough {
+            layer_rules.push(unmapped_to_rule((*layer).clone(), assignments));
+        }
+    }
+
+    layer_rules.extend(home_row_mods_to_rules(&configuration.home_row_mods));
+
+    let labels = layer_labels(configuration);
+    if !labels.is_empty() {
+        layer_rules = layer_rules
+            .into_iter()
+            .map(|r| {
+                r.with_layer_notifications(
+                    NOTIFICATION_ID,
+                    &layer_names,
+                    &labels,
+                    !configuration.settings.exclusive_layers,
+                )
+            })
+            .collect();
+    }
+
+    if let Some(threshold) = configuration.settings.simultaneous_threshold {
+        layer_rules = layer_rules
+            .into_iter()
+            .map(|r| r.with_simultaneous_threshold(threshold))
+            .collect();
+    }
+
+    if configuration.settings.exclusive_layers {
+        layer_rules = layer_rules
+            .into_iter()
+            .map(|r| r.with_exclusive_layers(LAYER_VARIABLE, &layer_names))
+            .collect();
+    }
 
     let complex_modifications = ComplexModifications {
         rules: Some(layer_rules),
@@ -33,40 +105,308 @@ pub fn convert_configuration(configuration: &Configuration) -> KarabinerConfig {
     let name = DEFAULT_PROFILE_NAME.to_string();
     let selected = true;
 
-    KarabinerConfig {
+    Ok(KarabinerConfig {
         profiles: vec![Profile {
             complex_modifications,
             devices: Some(devices),
             name,
             selected,
         }],
-    }
+    })
 }
 
 pub fn layer_to_rule(layer: Layer) -> Rule {
-    Rule::set_environment(layer.name, layer.keys.into())
+    let rule = Rule::set_environment(layer.name.clone(), layer.keys.into());
+    match layer.settings.timeout {
+        Some(timeout) => rule.with_timeout(layer.name, timeout),
+        None => rule,
+    }
+}
+
+fn with_layer_timeout(rule: Rule, layer: &str, layers: &[&Layer]) -> Rule {
+    match layers
+        .iter()
+        .find(|l| l.name == layer)
+        .map(|l| l.settings.timeout)
+    {
+        Some(Some(timeout)) => rule.with_timeout(layer.to_string(), timeout),
+        _ => rule,
+    }
+}
+
+/// Home row modifiers come after the layer bindings, so that bindings of active layers win. The
+/// typing streak is tracked on all other character keys.
+fn home_row_mods_to_rules(home_row_mods: &HomeRowMods) -> Vec<Rule> {
+    if home_row_mods.keys.is_empty() {
+        return vec![];
+    }
+
+    let streak_keys = Key::iter()
+        .filter(|k| k.is_character() && !home_row_mods.keys.iter().any(|m| &m.key == k))
+        .collect();
+
+    vec![
+        Rule::home_row_mods(
+            &home_row_mods.keys,
+            home_row_mods.hold_timeout,
+            TYPING_STREAK_VARIABLE,
+            home_row_mods.typing_streak_timeout,
+        ),
+        Rule::typing_streak(
+            streak_keys,
+            TYPING_STREAK_VARIABLE,
+            home_row_mods.typing_streak_timeout,
+        ),
+    ]
+}
+
+/// Labels of the layers which show a notification while they are active, ordered by precedence.
+fn layer_labels(configuration: &Configuration) -> Vec<(String, String)> {
+    configuration
+        .layers
+        .by_priority()
+        .into_iter()
+        .filter(|l| l.name != BASE_LAYER)
+        .filter_map(|l| {
+            let notification = match &l.settings.notification {
+                Some(notification) => notification.clone(),
+                None if configuration.settings.notifications => Notification::LayerName,
+                None => Notification::Hidden,
+            };
+            match notification {
+                Notification::Hidden => None,
+                Notification::LayerName => Some((l.name.clone(), l.name.clone())),
+                Notification::Label(label) => Some((l.name.clone(), label)),
+            }
+        })
+        .collect()
+}
+
+/// Bindings of simultaneous keys come first, followed by bindings with modifiers, so that the rules
+/// of single keys and keys without modifiers do not shadow them.
+fn layer_assignments_by_modifiers<'a>(
+    layer: &Layer,
+    assignments: &'a LayerAssignments,
+) -> Vec<&'a LayerAssignment> {
+    let mut layer_assignments: Vec<&LayerAssignment> = assignments
+        .assignments
+        .iter()
+        .filter(|a| a.layer.name == layer.name)
+        .collect();
+    layer_assignments.sort_by_key(|a| {
+        (
+            Reverse(a.key.is_simultaneous()),
+            Reverse(a.key.modifiers.len()),
+        )
+    });
+    layer_assignments
+}
+
+/// Conditions which disable the binding while a layer with a higher precedence that binds the same
+/// key is active. This keeps the precedence independent of the order of the rules.
+fn precedence_conditions(
+    assignment: &LayerAssignment,
+    layers: &[&Layer],
+    assignments: &LayerAssignments,
+) -> Vec<Condition> {
+    layers
+        .iter()
+        .take_while(|l| l.name != assignment.layer.name)
+        .filter(|l| {
+            assignments.assignments.iter().any(|a| {
+                a.layer.name == l.name && a.key == assignment.key && a.action != Action::Transparent
+            })
+        })
+        .map(|l| Condition::inactive(l.name.clone()))
+        .collect()
+}
+
+/// Creates a rule which leaves the layer and passes the key through for every key which is not bound
+/// in the layer. It has to be placed after the rules of the bindings.
+fn unmapped_to_rule(layer: Layer, assignments: &LayerAssignments) -> Rule {
+    let bound_keys: Vec<&Key> = assignments
+        .assignments
+        .iter()
+        .filter(|a| a.layer.name == layer.name)
+        .filter_map(|a| a.key.as_key())
+        .collect();
+    let unmapped_keys = Key::iter()
+        .filter(|k| k.is_passthrough() && !bound_keys.contains(&k))
+        .collect();
+    Rule::exit_and_passthrough(layer.name, unmapped_keys)
+}
+
+/// Converts an action which is combined with others into the rule it would have on its own.
+fn combined_action_to_rule(
+    layer_assignment: &LayerAssignment,
+    action: Action,
+    layout: &KeyboardLayout,
+    layers: &[&Layer],
+) -> Result<Rule> {
+    let assignment = LayerAssignment {
+        action,
+        ..layer_assignment.clone()
+    };
+    layer_assignment_to_rule(assignment, layout, layers)?
+        .ok_or_else(|| anyhow!("Transparent actions cannot be combined with other actions"))
 }
 
-fn layer_assignment_to_rule(layer_assignment: LayerAssignment) -> Rule {
-    match layer_assignment.action {
+/// `layers` are used to look up the timeout of a layer which is entered by the binding.
+fn layer_assignment_to_rule(
+    layer_assignment: LayerAssignment,
+    layout: &KeyboardLayout,
+    layers: &[&Layer],
+) -> Result<Option<Rule>> {
+    match layer_assignment.action.clone() {
+        Action::DoubleTap(double_tap) => {
+            let variable = format!(
+                "karamapper_double_tap_{}_{}",
+                layer_assignment.layer.name, layer_assignment.key
+            );
+            return Ok(Some(Rule::double_tap(
+                combined_action_to_rule(&layer_assignment, *double_tap.single, layout, layers)?,
+                combined_action_to_rule(&layer_assignment, *double_tap.double, layout, layers)?,
+                variable,
+                double_tap.timeout,
+            )));
+        }
+        Action::LongPress(long_press) => {
+            return Ok(Some(Rule::long_press(
+                combined_action_to_rule(&layer_assignment, *long_press.tap, layout, layers)?,
+                combined_action_to_rule(&layer_assignment, *long_press.hold, layout, layers)?,
+                long_press.threshold,
+            )));
+        }
+        _ => {}
+    }
+    let threshold = layer_assignment.key.threshold;
+    let pass_modifiers = layer_assignment.pass_modifiers;
+    let rule = match layer_assignment.action {
         Action::LayerRemap(remaps) => Rule::set_keymapping_in_layer(
             layer_assignment.layer.name,
             layer_assignment.key.into(),
-            remaps.to.into(),
+            remaps.into(),
             layer_assignment.next_layer,
         ),
-        Action::Command(command) => Rule::set_command_in_layer(
+        Action::Macro(macro_) => Rule::set_keymappings_in_layer(
             layer_assignment.layer.name,
             layer_assignment.key.into(),
-            command.into(),
+            macro_.steps.into_iter().map(|s| s.into()).collect(),
+            layer_assignment.next_layer,
+        ),
+        Action::TypeText(text) => Rule::type_text_in_layer(
+            layer_assignment.layer.name,
+            layer_assignment.key.into(),
+            text_to_key_mappings(&text.text, layout)?,
+            text.text,
             layer_assignment.next_layer,
         ),
-        Action::LayerShift(layer) => Rule::switch_layer(
-            layer.move_layer,
+        Action::OpenApp(app) => Rule::set_targets_in_layer(
+            format!("Open application {}", app),
             layer_assignment.layer.name,
             layer_assignment.key.into(),
+            vec![app.into()],
+            layer_assignment.next_layer,
         ),
-    }
+        Action::OpenUrl(url) => Rule::set_targets_in_layer(
+            format!("Open URL {}", url.url),
+            layer_assignment.layer.name,
+            layer_assignment.key.into(),
+            vec![ManipulationTarget::ShellCommand(url.into())],
+            layer_assignment.next_layer,
+        ),
+        Action::AppleScript(script) => Rule::set_targets_in_layer(
+            "Run AppleScript".to_string(),
+            layer_assignment.layer.name,
+            layer_assignment.key.into(),
+            vec![ManipulationTarget::ShellCommand(script.into())],
+            layer_assignment.next_layer,
+        ),
+        Action::Shortcut(shortcut) => Rule::set_targets_in_layer(
+            format!("Run shortcut {}", shortcut.name),
+            layer_assignment.layer.name,
+            layer_assignment.key.into(),
+            vec![ManipulationTarget::ShellCommand(shortcut.into())],
+            layer_assignment.next_layer,
+        ),
+        Action::SelectInputSource(input_source) => Rule::set_targets_in_layer(
+            format!(
+                "Select input source {}",
+                input_source
+                    .language
+                    .as_ref()
+                    .or(input_source.input_source_id.as_ref())
+                    .ok_or_else(|| anyhow!("Missing language or input_source_id"))?
+            ),
+            layer_assignment.layer.name,
+            layer_assignment.key.into(),
+            vec![input_source.into()],
+            layer_assignment.next_layer,
+        ),
+        Action::Sticky(sticky) => Rule::set_targets_in_layer(
+            format!("Sticky modifier {} {}", sticky.modifier, sticky.mode),
+            layer_assignment.layer.name,
+            layer_assignment.key.into(),
+            vec![ManipulationTarget::StickyModifier(StickyModifier::new(
+                sticky.modifier,
+                sticky.mode,
+            ))],
+            layer_assignment.next_layer,
+        ),
+        Action::TapHold(tap_hold) => Rule::tap_hold_in_layer(
+            layer_assignment.layer.name,
+            layer_assignment.key.into(),
+            tap_hold.tap.into(),
+            ToKeyMapping {
+                options: tap_hold.options.into(),
+                ..tap_hold.hold.into()
+            },
+            tap_hold.timeout,
+            layer_assignment.next_layer,
+        ),
+        Action::Mouse(mouse) => Rule::set_targets_in_layer(
+            format!("Mouse {}", mouse),
+            layer_assignment.layer.name,
+            layer_assignment.key.into(),
+            vec![mouse.into()],
+            layer_assignment.next_layer,
+        ),
+        Action::Command(command) => Rule::set_command_in_layer(
+            layer_assignment.layer.name,
+            layer_assignment.key.into(),
+            command.into(),
+            layer_assignment.next_layer,
+        ),
+        Action::LayerShift(layer) => with_layer_timeout(
+            Rule::switch_layer(
+                layer.move_layer.clone(),
+                layer_assignment.layer.name,
+                layer_assignment.key.into(),
+            ),
+            &layer.move_layer,
+            layers,
+        ),
+        Action::DoubleTap(_) | Action::LongPress(_) | Action::Transparent => return Ok(None),
+    };
+    let rule = match threshold {
+        Some(threshold) => rule.with_simultaneous_threshold(threshold),
+        None => rule,
+    };
+    Ok(Some(match pass_modifiers {
+        true => rule.with_optional_modifiers(),
+        false => rule,
+    }))
+}
+
+fn text_to_key_mappings(text: &str, layout: &KeyboardLayout) -> Result<Vec<ToKeyMapping>> {
+    text.chars()
+        .map(|c| {
+            layout
+                .keystroke(c)
+                .map(ToKeyMapping::from)
+                .ok_or_else(|| anyhow!("Character {:?} can not be typed with layout {}", c, layout))
+        })
+        .collect()
 }
 
 fn remaps_to_simple_modifications(remaps: SimpleRemaps) -> Vec<SimpleModification> {
@@ -78,15 +418,31 @@ fn remaps_to_simple_modifications(remaps: SimpleRemaps) -> Vec<SimpleModificatio
 }
 
 fn remap_to_simple_modification(remap: SimpleRemap) -> SimpleModification {
+    let options: ToEventOptions = remap.options.into();
     SimpleModification {
         from: remap.from.into(),
-        to: remap.to.iter().map(|k| k.clone().into()).collect(),
+        to: remap
+            .to
+            .into_iter()
+            .map(|key_code| SimpleToKeyMapping {
+                key_code,
+                options: options.clone(),
+            })
+            .collect(),
     }
 }
 
 impl From<Key> for SimpleKeyMapping {
     fn from(key: Key) -> Self {
-        SimpleKeyMapping { key_code: key }
+        SimpleKeyMapping {
+            key_code: key.into(),
+        }
+    }
+}
+
+impl From<KeyCode> for SimpleKeyMapping {
+    fn from(key_code: KeyCode) -> Self {
+        SimpleKeyMapping { key_code }
     }
 }
 
@@ -101,21 +457,65 @@ impl From<Key> for Modifiers {
 
 impl From<Key> for FromKeyMapping {
     fn from(value: Key) -> Self {
+        KeyCode::from(value).into()
+    }
+}
+
+impl From<KeyCode> for FromKeyMapping {
+    fn from(value: KeyCode) -> Self {
         FromKeyMapping {
-            key_code: value,
-            modifiers: None,
+            key_code: Some(value),
+            ..Default::default()
+        }
+    }
+}
+
+impl From<KeyCombo> for FromKeyMapping {
+    fn from(combo: KeyCombo) -> Self {
+        let simultaneous = combo.is_simultaneous();
+        let modifiers = (!combo.modifiers.is_empty()).then_some(Modifiers {
+            mandatory: Some(combo.modifiers),
+            optional: None,
+        });
+
+        match simultaneous {
+            true => FromKeyMapping {
+                key_code: None,
+                simultaneous: Some(combo.keys.into_iter().map(SimpleKeyMapping::from).collect()),
+                simultaneous_options: combo.key_down_order.map(|order| SimultaneousOptions {
+                    key_down_order: Some(order),
+                }),
+                modifiers,
+            },
+            false => FromKeyMapping {
+                key_code: combo.keys.into_iter().next(),
+                modifiers,
+                ..Default::default()
+            },
         }
     }
 }
 
 impl From<Vec<Key>> for FromKeyMapping {
     fn from(keys: Vec<Key>) -> Self {
-        let key_code = keys.first().unwrap().clone();
-        let modifiers = match keys.len() {
-            1 => None,
-            _ => {
+        keys.into_iter()
+            .map(KeyCode::from)
+            .collect::<Vec<_>>()
+            .into()
+    }
+}
+
+impl From<Vec<KeyCode>> for FromKeyMapping {
+    /// The key which is not a modifier is pressed while the others are held as modifiers, the same
+    /// as for a [`ToKeyMapping`]. If all keys are modifiers, the first one is pressed.
+    fn from(mut keys: Vec<KeyCode>) -> Self {
+        let position = keys.iter().position(|k| !k.is_modifier()).unwrap_or(0);
+        let key_code = Some(keys.remove(position));
+        let modifiers = match keys.is_empty() {
+            true => None,
+            false => {
                 let modifiers = Modifiers {
-                    mandatory: Some(keys.iter().skip(1).map(|k| k.clone()).collect()),
+                    mandatory: Some(key_modifiers(&keys)),
                     optional: None,
                 };
 
@@ -126,24 +526,68 @@ impl From<Vec<Key>> for FromKeyMapping {
         Self {
             key_code,
             modifiers,
+            ..Default::default()
         }
     }
 }
+
 impl From<Vec<Key>> for ToKeyMapping {
     fn from(keys: Vec<Key>) -> Self {
-        let key_code = keys.first().unwrap().clone();
-        let modifiers = match keys.len() {
-            1 => vec![],
-            _ => keys.iter().skip(1).map(|k| k.clone()).collect(),
-        };
+        keys.into_iter()
+            .map(KeyCode::from)
+            .collect::<Vec<_>>()
+            .into()
+    }
+}
+
+impl From<Vec<KeyCode>> for ToKeyMapping {
+    /// The first key which is not a modifier is sent together with the other keys as modifiers.
+    /// If all keys are modifiers, the first one is sent.
+    fn from(mut keys: Vec<KeyCode>) -> Self {
+        let position = keys.iter().position(|k| !k.is_modifier()).unwrap_or(0);
+        let key_code = keys.remove(position);
+        let modifiers = key_modifiers(&keys);
 
         Self {
             key_code,
             modifiers,
+            options: ToEventOptions::default(),
+        }
+    }
+}
+
+/// Consumer keys cannot act as modifiers and are rejected when the keys are parsed.
+fn key_modifiers(keys: &[KeyCode]) -> Vec<Key> {
+    keys.iter().filter_map(KeyCode::as_key).cloned().collect()
+}
+
+impl From<LayerRemap> for ToKeyMapping {
+    fn from(remap: LayerRemap) -> Self {
+        let mut mapping: ToKeyMapping = remap.to.into();
+        mapping.options = remap.options.into();
+        mapping
+    }
+}
+
+impl From<KeyOptions> for ToEventOptions {
+    fn from(options: KeyOptions) -> Self {
+        ToEventOptions {
+            hold_down_milliseconds: options.hold_down_milliseconds,
+            halt: options.halt,
+            repeat: options.repeat,
+            lazy: options.lazy,
         }
     }
 }
 
+impl From<MacroStep> for ToKeyMapping {
+    fn from(step: MacroStep) -> Self {
+        let mut mapping: ToKeyMapping = step.keys.into();
+        mapping.options.hold_down_milliseconds = step.hold_down_milliseconds;
+        mapping
+    }
+}
+
 impl From<Command> for ShellCommand {
     fn from(command: Command) -> Self {
         ShellCommand {
@@ -152,156 +596,914 @@ impl From<Command> for ShellCommand {
     }
 }
 
-#[cfg(test)]
-mod tests {
+impl From<OpenApp> for ManipulationTarget {
+    /// Karabiner's `open_application` needs a bundle identifier or a path, so applications given by
+    /// name are always opened with `open -a`.
+    #[cfg(feature = "software-function")]
+    fn from(app: OpenApp) -> Self {
+        let open_application = match app {
+            OpenApp::Name(_) => return open_app_shell_command(app),
+            OpenApp::BundleId(bundle_id) => OpenApplication {
+                bundle_identifier: Some(bundle_id),
+                file_path: None,
+            },
+            OpenApp::FilePath(file_path) => OpenApplication {
+                bundle_identifier: None,
+                file_path: Some(file_path),
+            },
+        };
+        ManipulationTarget::SoftwareFunction(SoftwareFunction {
+            software_function: SoftwareFunctionValues::OpenApplication(open_application),
+        })
+    }
 
-    use super::*;
-    use pretty_assertions::assert_eq;
+    #[cfg(not(feature = "software-function"))]
+    fn from(app: OpenApp) -> Self {
+        open_app_shell_command(app)
+    }
+}
 
-    #[test]
-    fn test_remaps_to_simple_modifications() {
-        let remaps = SimpleRemaps {
-            remaps: vec![
-                SimpleRemap {
-                    from: Key::CapsLock,
-                    to: vec![Key::LeftCommand],
-                },
-                SimpleRemap {
-                    from: Key::V,
-                    to: vec![Key::LeftCommand, Key::V],
-                },
-            ],
-        };
+fn open_app_shell_command(app: OpenApp) -> ManipulationTarget {
+    let shell_command = match app {
+        OpenApp::Name(name) => format!("open -a {}", shell_quote(&name)),
+        OpenApp::BundleId(bundle_id) => format!("open -b {}", shell_quote(&bundle_id)),
+        OpenApp::FilePath(file_path) => format!("open {}", shell_quote(&file_path)),
+    };
+    ManipulationTarget::ShellCommand(ShellCommand { shell_command })
+}
 
-        let simple_modifications = remaps_to_simple_modifications(remaps);
+impl From<OpenUrl> for ShellCommand {
+    fn from(url: OpenUrl) -> Self {
+        ShellCommand {
+            shell_command: format!("open {}", shell_quote(&url.url)),
+        }
+    }
+}
 
-        assert_eq!(simple_modifications.len(), 2);
-        assert_eq!(simple_modifications[0].from.key_code, Key::CapsLock);
-        assert_eq!(simple_modifications[0].to.len(), 1);
-        assert_eq!(simple_modifications[0].to[0].key_code, Key::LeftCommand);
+impl From<AppleScript> for ShellCommand {
+    /// Every line of an inline script is passed as its own `-e` argument.
+    fn from(script: AppleScript) -> Self {
+        let shell_command = match script {
+            AppleScript::Inline(script) => {
+                let lines: Vec<String> = script
+                    .lines()
+                    .map(|line| format!("-e {}", shell_quote(line)))
+                    .collect();
+                format!("osascript {}", lines.join(" "))
+            }
+            AppleScript::File(file) => format!("osascript {}", shell_quote_path(&file)),
+        };
+        ShellCommand { shell_command }
+    }
+}
 
-        assert_eq!(simple_modifications[1].from.key_code, Key::V);
-        assert_eq!(simple_modifications[1].to.len(), 2);
-        assert_eq!(simple_modifications[1].to[0].key_code, Key::LeftCommand);
-        assert_eq!(simple_modifications[1].to[1].key_code, Key::V);
+impl From<Shortcut> for ShellCommand {
+    fn from(shortcut: Shortcut) -> Self {
+        ShellCommand {
+            shell_command: format!("shortcuts run {}", shell_quote(&shortcut.name)),
+        }
     }
+}
 
-    #[test]
-    fn test_layer_creates_rule() {
-        let name = String::from("layer1");
-        let keys = vec![Key::LeftCommand];
+impl From<SelectInputSource> for ManipulationTarget {
+    /// Matches the values exactly instead of treating them as regular expressions.
+    fn from(input_source: SelectInputSource) -> Self {
+        let exact = |value: String| format!("^{}$", regex_escape(&value));
+        ManipulationTarget::InputSource(InputSource {
+            select_input_source: InputSourceValues {
+                language: input_source.language.map(exact),
+                input_source_id: input_source.input_source_id.map(exact),
+            },
+        })
+    }
+}
 
-        let layer = Layer {
-            name: name.clone(),
-            keys: keys.clone(),
+fn regex_escape(value: &str) -> String {
+    value
+        .chars()
+        .fold(String::with_capacity(value.len()), |mut escaped, c| {
+            if r"\.+*?()|[]{}^$".contains(c) {
+                escaped.push('\\');
+            }
+            escaped.push(c);
+            escaped
+        })
+}
+
+impl From<Mouse> for ManipulationTarget {
+    fn from(mouse: Mouse) -> Self {
+        let mouse_key = match mouse {
+            Mouse::Click(button) => {
+                return ManipulationTarget::PointingButton(PointingButton {
+                    pointing_button: button,
+                })
+            }
+            Mouse::Move { direction, speed } => {
+                let speed = i32::try_from(speed).unwrap_or(i32::MAX);
+                match direction {
+                    Direction::Left => MouseKeyValues {
+                        x: Some(-speed),
+                        ..Default::default()
+                    },
+                    Direction::Right => MouseKeyValues {
+                        x: Some(speed),
+                        ..Default::default()
+                    },
+                    Direction::Up => MouseKeyValues {
+                        y: Some(-speed),
+                        ..Default::default()
+                    },
+                    Direction::Down => MouseKeyValues {
+                        y: Some(speed),
+                        ..Default::default()
+                    },
+                }
+            }
+            // Karabiner scrolls up and left for negative vertical and positive horizontal values.
+            Mouse::Scroll { direction, speed } => {
+                let speed = i32::try_from(speed).unwrap_or(i32::MAX);
+                match direction {
+                    Direction::Left => MouseKeyValues {
+                        horizontal_wheel: Some(speed),
+                        ..Default::default()
+                    },
+                    Direction::Right => MouseKeyValues {
+                        horizontal_wheel: Some(-speed),
+                        ..Default::default()
+                    },
+                    Direction::Up => MouseKeyValues {
+                        vertical_wheel: Some(-speed),
+                        ..Default::default()
+                    },
+                    Direction::Down => MouseKeyValues {
+                        vertical_wheel: Some(speed),
+                        ..Default::default()
+                    },
+                }
+            }
+            Mouse::SpeedMultiplier(multiplier) => MouseKeyValues {
+                speed_multiplier: Some(multiplier),
+                ..Default::default()
+            },
         };
+        ManipulationTarget::MouseKey(MouseKey { mouse_key })
+    }
+}
 
-        let expected_rule = Rule::set_environment(name, keys.into());
-        let rule = layer_to_rule(layer);
+/// Quotes the value so that the shell passes it on as a single argument.
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
 
-        assert_eq!(rule, expected_rule);
-        assert_eq!(
-            rule.manipulators.first().unwrap().from.key_code,
-            Key::LeftCommand
-        );
-        assert_eq!(
-            rule.manipulators.clone().first().unwrap().from.modifiers,
-            None
-        );
-        assert_eq!(rule.description, Some("Change to layer1".to_string()));
-        assert_eq!(rule.manipulators.first().unwrap().conditions, Some(vec![]));
+/// Quotes the path like `shell_quote`, but keeps a leading `~/` unquoted so that the shell expands
+/// it to the home directory.
+fn shell_quote_path(path: &str) -> String {
+    match path.strip_prefix("~/") {
+        Some(path) => format!("~/{}", shell_quote(path)),
+        None => shell_quote(path),
     }
+}
 
-    #[test]
-    fn test_layer_with_two_keys_creates_rule() {
-        let name = String::from("layer1");
-        let keys = vec![Key::LeftCommand, Key::V];
+#[cfg(test)]
+mod tests {
 
-        let layer = Layer {
-            name: name.clone(),
-            keys: keys.clone(),
-        };
+    use super::*;
+    use crate::keys::{ConsumerKey, KeyDownOrder, StickyModifierMode};
+    use pretty_assertions::assert_eq;
 
-        let expected_rule = Rule::set_environment(name, keys.into());
-        let rule = layer_to_rule(layer);
+    /// Binds the action to the key in `layer1`, which is entered with left_command.
+    fn assignment(key: impl Into<KeyCombo>, action: Action) -> LayerAssignment {
+        LayerAssignment {
+            layer: Layer {
+                name: "layer1".to_string(),
+                keys: Key::LeftCommand.into(),
+                settings: LayerSettings::default(),
+            },
+            key: key.into(),
+            action,
+            next_layer: None,
+            description: None,
+            pass_modifiers: false,
+        }
+    }
+
+    #[test]
+    fn test_consumer_keys_serialize_as_consumer_key_code() {
+        let from: FromKeyMapping =
+            vec![KeyCode::from(ConsumerKey::Eject), Key::LeftCommand.into()].into();
+        let to: ToKeyMapping = vec![KeyCode::from(ConsumerKey::PlayOrPause)].into();
+        let simple = remap_to_simple_modification(SimpleRemap {
+            from: Key::F8.into(),
+            to: vec![ConsumerKey::PlayOrPause.into()],
+            options: KeyOptions::default(),
+        });
 
-        assert_eq!(rule, expected_rule);
         assert_eq!(
-            rule.manipulators.first().unwrap().from.key_code,
-            Key::LeftCommand
+            serde_json::to_value(from).unwrap(),
+            serde_json::json!({
+                "consumer_key_code": "eject",
+                "modifiers": { "mandatory": ["left_command"] }
+            })
         );
         assert_eq!(
-            rule.manipulators.first().unwrap().from.modifiers,
-            Some(Modifiers {
-                mandatory: Some(vec![Key::V]),
-                optional: None,
+            serde_json::to_value(to).unwrap(),
+            serde_json::json!({ "consumer_key_code": "play_or_pause", "modifiers": [] })
+        );
+        assert_eq!(
+            serde_json::to_value(simple).unwrap(),
+            serde_json::json!({
+                "from": { "key_code": "f8" },
+                "to": [{ "consumer_key_code": "play_or_pause" }]
             })
         );
     }
 
     #[test]
-    fn test_layer_assignment_to_remap() {
-        let layer_assignment = LayerAssignment {
-            layer: Layer {
-                name: "layer1".to_string(),
-                keys: vec![Key::LeftCommand],
-            },
-            key: Key::H,
-            action: Action::LayerRemap(LayerRemap {
-                to: vec![Key::Escape, Key::LeftShift],
+    fn test_tap_hold_with_modifiers() {
+        let rule = Rule::tap_hold_in_layer(
+            BASE_LAYER.to_string(),
+            Key::CapsLock.into(),
+            vec![Key::Escape].into(),
+            vec![Key::LeftCommand, Key::LeftShift].into(),
+            200,
+            None,
+        );
+
+        assert_eq!(
+            serde_json::to_value(&rule.manipulators[0]).unwrap(),
+            serde_json::json!({
+                "conditions": [],
+                "from": { "key_code": "caps_lock" },
+                "to": [{ "key_code": "left_command", "modifiers": ["left_shift"] }],
+                "to_if_alone": [{ "key_code": "escape", "modifiers": [] }],
+                "parameters": {
+                    "basic.to_if_alone_timeout_milliseconds": 200,
+                    "basic.to_if_held_down_threshold_milliseconds": 200
+                },
+                "type": "basic"
+            })
+        );
+    }
+
+    #[test]
+    fn test_tap_hold_with_key() {
+        let rule = Rule::tap_hold_in_layer(
+            "layer1".to_string(),
+            Key::A.into(),
+            vec![Key::A].into(),
+            vec![Key::B, Key::LeftCommand].into(),
+            500,
+            None,
+        );
+        let manipulator = &rule.manipulators[0];
+
+        assert_eq!(manipulator.to, Some(vec![]));
+        assert_eq!(
+            manipulator.to_if_alone,
+            Some(vec![ManipulationTarget::KeyMapping(vec![Key::A].into())])
+        );
+        assert_eq!(
+            manipulator.to_if_held_down,
+            Some(vec![ManipulationTarget::KeyMapping(
+                vec![Key::B, Key::LeftCommand].into()
+            )])
+        );
+        assert_eq!(
+            manipulator.parameters,
+            Some(Parameters {
+                to_if_alone_timeout_milliseconds: Some(500),
+                to_if_held_down_threshold_milliseconds: Some(500),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_tap_hold_binding_with_options() {
+        let layer_assignment = assignment(
+            Key::A,
+            Action::TapHold(TapHold {
+                tap: vec![Key::A.into()],
+                hold: vec![Key::B.into()],
+                timeout: 500,
+                options: KeyOptions {
+                    halt: Some(true),
+                    ..Default::default()
+                },
+            }),
+        );
+
+        let rule = layer_assignment_to_rule(layer_assignment, &KeyboardLayout::default(), &[])
+            .unwrap()
+            .unwrap();
+        let manipulator = serde_json::to_value(&rule.manipulators[0]).unwrap();
+
+        assert_eq!(
+            manipulator["to_if_held_down"],
+            serde_json::json!([{ "key_code": "b", "modifiers": [], "halt": true }])
+        );
+        assert_eq!(
+            manipulator["to_if_alone"],
+            serde_json::json!([{ "key_code": "a", "modifiers": [] }])
+        );
+    }
+
+    #[test]
+    fn test_home_row_mods() {
+        let home_row_mods = HomeRowMods {
+            keys: vec![
+                HomeRowMod {
+                    key: Key::A,
+                    modifier: Key::LeftControl,
+                    hold_timeout: Some(250),
+                },
+                HomeRowMod {
+                    key: Key::D,
+                    modifier: Key::LeftShift,
+                    hold_timeout: None,
+                },
+            ],
+            ..Default::default()
+        };
+
+        let rules = home_row_mods_to_rules(&home_row_mods);
+
+        let manipulators = &rules[0].manipulators;
+        assert_eq!(manipulators.len(), 4);
+        assert_eq!(
+            manipulators[3]
+                .parameters
+                .as_ref()
+                .and_then(|p| p.to_if_held_down_threshold_milliseconds),
+            Some(200)
+        );
+        assert_eq!(
+            serde_json::to_value(&manipulators[0]).unwrap(),
+            serde_json::json!({
+                "conditions": [
+                    { "name": "karamapper_typing_streak", "type": "variable_if", "value": 1 }
+                ],
+                "from": { "key_code": "a", "modifiers": { "optional": ["any"] } },
+                "to": [
+                    { "set_variable": { "name": "karamapper_typing_streak", "value": 1 } },
+                    { "key_code": "a", "modifiers": [] }
+                ],
+                "to_delayed_action": {
+                    "to_if_canceled": [
+                        { "set_variable": { "name": "karamapper_typing_streak", "value": 0 } }
+                    ],
+                    "to_if_invoked": [
+                        { "set_variable": { "name": "karamapper_typing_streak", "value": 0 } }
+                    ]
+                },
+                "parameters": { "basic.to_delayed_action_delay_milliseconds": 150 },
+                "type": "basic"
+            })
+        );
+        assert_eq!(
+            serde_json::to_value(&manipulators[1]).unwrap(),
+            serde_json::json!({
+                "conditions": [],
+                "from": { "key_code": "a", "modifiers": { "optional": ["any"] } },
+                "to": [],
+                "to_delayed_action": {
+                    "to_if_canceled": [{ "key_code": "a", "modifiers": [] }],
+                    "to_if_invoked": []
+                },
+                "to_if_alone": [{ "key_code": "a", "modifiers": [], "halt": true }],
+                "to_if_held_down": [{ "key_code": "left_control", "modifiers": [] }],
+                "parameters": {
+                    "basic.to_delayed_action_delay_milliseconds": 250,
+                    "basic.to_if_alone_timeout_milliseconds": 250,
+                    "basic.to_if_held_down_threshold_milliseconds": 250
+                },
+                "type": "basic"
+            })
+        );
+
+        let streak_keys: Vec<&KeyCode> = rules[1]
+            .manipulators
+            .iter()
+            .filter_map(|m| m.from.key_code.as_ref())
+            .collect();
+        assert!(streak_keys.contains(&&Key::S.into()));
+        assert!(!streak_keys.contains(&&Key::A.into()));
+        assert!(home_row_mods_to_rules(&HomeRowMods::default()).is_empty());
+    }
+
+    #[test]
+    fn test_typing_streak_ends_on_other_keys() {
+        let home_row_mods = HomeRowMods {
+            keys: vec![HomeRowMod {
+                key: Key::A,
+                modifier: Key::LeftControl,
+                hold_timeout: None,
+            }],
+            ..Default::default()
+        };
+
+        let rules = home_row_mods_to_rules(&home_row_mods);
+
+        let reset = vec![ManipulationTarget::set_inactive(
+            TYPING_STREAK_VARIABLE.to_string(),
+        )];
+        assert!(rules[1].manipulators.iter().all(|m| m
+            .to_delayed_action
+            .as_ref()
+            .is_some_and(|d| d.to_if_canceled == reset && d.to_if_invoked == reset)));
+        assert!(!rules[1]
+            .manipulators
+            .iter()
+            .any(|m| m.from.key_code == Some(Key::ReturnOrEnter.into())));
+    }
+
+    #[test]
+    fn test_double_tap_binding() {
+        let layer_assignment = assignment(
+            Key::Escape,
+            Action::DoubleTap(DoubleTap {
+                single: Box::new(Action::LayerRemap(LayerRemap {
+                    to: vec![Key::Escape.into()],
+                    options: KeyOptions::default(),
+                })),
+                double: Box::new(Action::LayerRemap(LayerRemap {
+                    to: vec![Key::Q.into(), Key::LeftCommand.into()],
+                    options: KeyOptions::default(),
+                })),
+                timeout: 250,
+            }),
+        );
+        let variable = "karamapper_double_tap_layer1_escape".to_string();
+
+        let rule = layer_assignment_to_rule(layer_assignment, &KeyboardLayout::default(), &[])
+            .unwrap()
+            .unwrap();
+        let [double, single] = &rule.manipulators[..] else {
+            panic!("Expected two manipulators");
+        };
+
+        assert_eq!(
+            double.conditions.as_ref().unwrap().last(),
+            Some(&Condition::active(variable.clone()))
+        );
+        assert_eq!(
+            double.to.as_ref().unwrap()[0],
+            ManipulationTarget::set_inactive(variable.clone())
+        );
+        assert_eq!(
+            single.to.as_ref().unwrap()[0],
+            ManipulationTarget::set_active(variable.clone())
+        );
+        assert_eq!(
+            single.to_delayed_action,
+            Some(DelayedAction {
+                to_if_invoked: vec![ManipulationTarget::set_inactive(variable.clone())],
+                to_if_canceled: vec![ManipulationTarget::set_inactive(variable)],
+            })
+        );
+        assert_eq!(
+            single
+                .parameters
+                .as_ref()
+                .unwrap()
+                .to_delayed_action_delay_milliseconds,
+            Some(250)
+        );
+    }
+
+    #[test]
+    fn test_double_tap_keeps_layer_timeout() {
+        let sublayer = Layer {
+            name: "layer1.w".to_string(),
+            keys: KeyCombo::default(),
+            settings: LayerSettings {
+                timeout: Some(1000),
+                ..Default::default()
+            },
+        };
+        let layer_assignment = assignment(
+            Key::W,
+            Action::DoubleTap(DoubleTap {
+                single: Box::new(Action::LayerShift(LayerShift {
+                    move_layer: sublayer.name.clone(),
+                })),
+                double: Box::new(Action::LayerRemap(LayerRemap {
+                    to: vec![Key::W.into(), Key::LeftCommand.into()],
+                    options: KeyOptions::default(),
+                })),
+                timeout: 250,
             }),
+        );
+        let variable = "karamapper_double_tap_layer1_w".to_string();
+
+        let rule =
+            layer_assignment_to_rule(layer_assignment, &KeyboardLayout::default(), &[&sublayer])
+                .unwrap()
+                .unwrap();
+        let single = &rule.manipulators[1];
+
+        assert_eq!(
+            single.to_delayed_action,
+            Some(DelayedAction {
+                to_if_invoked: vec![
+                    ManipulationTarget::set_inactive(variable.clone()),
+                    ManipulationTarget::set_inactive(sublayer.name.clone()),
+                ],
+                to_if_canceled: vec![ManipulationTarget::set_inactive(variable)],
+            })
+        );
+        assert_eq!(
+            single
+                .parameters
+                .as_ref()
+                .unwrap()
+                .to_delayed_action_delay_milliseconds,
+            Some(1000)
+        );
+    }
+
+    #[test]
+    fn test_long_press_binding() {
+        let layer_assignment = LayerAssignment {
             next_layer: Some(BASE_LAYER.to_string()),
-            description: None,
+            ..assignment(
+                Key::S,
+                Action::LongPress(LongPress {
+                    tap: Box::new(Action::Command(Command {
+                        value: "open -a Slack".to_string(),
+                    })),
+                    hold: Box::new(Action::LayerRemap(LayerRemap {
+                        to: vec![Key::K.into(), Key::LeftCommand.into()],
+                        options: KeyOptions::default(),
+                    })),
+                    threshold: 400,
+                }),
+            )
+        };
+
+        let rule = layer_assignment_to_rule(layer_assignment, &KeyboardLayout::default(), &[])
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            rule.description,
+            Some("Run command open -a Slack, hold: Remap s to left_command+k".to_string())
+        );
+        assert_eq!(
+            serde_json::to_value(&rule.manipulators[0]).unwrap(),
+            serde_json::json!({
+                "conditions": [{ "name": "layer1", "type": "variable_if", "value": 1 }],
+                "from": { "key_code": "s" },
+                "to": [],
+                "to_delayed_action": {
+                    "to_if_canceled": [],
+                    "to_if_invoked": [{ "set_variable": { "name": "layer1", "value": 0 } }]
+                },
+                "to_if_alone": [{ "shell_command": "open -a Slack" }],
+                "to_if_held_down": [{ "key_code": "k", "modifiers": ["left_command"] }],
+                "parameters": {
+                    "basic.to_if_alone_timeout_milliseconds": 400,
+                    "basic.to_if_held_down_threshold_milliseconds": 400
+                },
+                "type": "basic"
+            })
+        );
+    }
+
+    #[test]
+    fn test_long_press_keeps_layer_timeout() {
+        let layer2 = Layer {
+            name: "layer2".to_string(),
+            keys: KeyCombo::default(),
+            settings: LayerSettings {
+                timeout: Some(1000),
+                ..Default::default()
+            },
+        };
+        let layer_assignment = assignment(
+            Key::W,
+            Action::LongPress(LongPress {
+                tap: Box::new(Action::LayerRemap(LayerRemap {
+                    to: vec![Key::W.into()],
+                    options: KeyOptions::default(),
+                })),
+                hold: Box::new(Action::LayerShift(LayerShift {
+                    move_layer: layer2.name.clone(),
+                })),
+                threshold: 400,
+            }),
+        );
+
+        let rule =
+            layer_assignment_to_rule(layer_assignment, &KeyboardLayout::default(), &[&layer2])
+                .unwrap()
+                .unwrap();
+        let manipulator = &rule.manipulators[0];
+
+        assert_eq!(
+            manipulator.to_delayed_action,
+            Some(DelayedAction {
+                to_if_invoked: vec![ManipulationTarget::set_inactive(layer2.name.clone())],
+                to_if_canceled: vec![],
+            })
+        );
+        assert_eq!(
+            manipulator
+                .parameters
+                .as_ref()
+                .unwrap()
+                .to_delayed_action_delay_milliseconds,
+            Some(1000)
+        );
+        assert!(manipulator
+            .to_if_held_down
+            .as_ref()
+            .unwrap()
+            .contains(&ManipulationTarget::set_active(layer2.name)));
+    }
+
+    #[test]
+    fn test_double_tap_transparent_fails() {
+        let layer_assignment = assignment(
+            Key::Escape,
+            Action::DoubleTap(DoubleTap {
+                single: Box::new(Action::LayerRemap(LayerRemap {
+                    to: vec![Key::Escape.into()],
+                    options: KeyOptions::default(),
+                })),
+                double: Box::new(Action::Transparent),
+                timeout: 250,
+            }),
+        );
+
+        assert!(
+            layer_assignment_to_rule(layer_assignment, &KeyboardLayout::default(), &[]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_simultaneous_binding() {
+        let layer_assignment = assignment(
+            KeyCombo {
+                keys: vec![Key::J.into(), Key::K.into()],
+                modifiers: vec![],
+                key_down_order: Some(KeyDownOrder::Insensitive),
+                threshold: Some(80),
+            },
+            Action::LayerRemap(LayerRemap {
+                to: vec![Key::Escape.into()],
+                options: KeyOptions::default(),
+            }),
+        );
+
+        let rule = layer_assignment_to_rule(layer_assignment, &KeyboardLayout::default(), &[])
+            .unwrap()
+            .unwrap()
+            .with_simultaneous_threshold(50);
+        let manipulator = &rule.manipulators[0];
+
+        assert_eq!(rule.description, Some("Remap j&k to escape".to_string()));
+        assert_eq!(
+            serde_json::to_value(&manipulator.from).unwrap(),
+            serde_json::json!({
+                "simultaneous": [{ "key_code": "j" }, { "key_code": "k" }],
+                "simultaneous_options": { "key_down_order": "insensitive" }
+            })
+        );
+        assert_eq!(
+            manipulator
+                .parameters
+                .as_ref()
+                .unwrap()
+                .simultaneous_threshold_milliseconds,
+            Some(80)
+        );
+    }
+
+    #[test]
+    fn test_layer_with_modifier_aliases() -> Result<()> {
+        let from = |keys: &str| -> Result<serde_json::Value> {
+            let layers = Layers::from_toml(&format!("layer = {:?}", keys).parse()?)?;
+            let rule = layer_to_rule(layers.layers[0].clone());
+            Ok(serde_json::to_value(&rule.manipulators[0].from)?)
+        };
+
+        assert_eq!(
+            from("hyper+l")?,
+            serde_json::json!({
+                "key_code": "l",
+                "modifiers": {
+                    "mandatory": ["left_command", "left_shift", "left_option", "left_control"]
+                }
+            })
+        );
+        assert_eq!(
+            from("command+m")?,
+            serde_json::json!({ "key_code": "m", "modifiers": { "mandatory": ["command"] } })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_layer_with_simultaneous_keys() {
+        let rule = layer_to_rule(Layer {
+            name: "nav".to_string(),
+            keys: KeyCombo {
+                keys: vec![Key::J.into(), Key::K.into()],
+                ..Default::default()
+            },
+            settings: LayerSettings::default(),
+        });
+
+        assert_eq!(
+            serde_json::to_value(&rule.manipulators[0].from).unwrap(),
+            serde_json::json!({ "simultaneous": [{ "key_code": "j" }, { "key_code": "k" }] })
+        );
+    }
+
+    #[test]
+    fn test_remaps_to_simple_modifications() {
+        let remaps = SimpleRemaps {
+            remaps: vec![
+                SimpleRemap {
+                    from: Key::CapsLock.into(),
+                    to: vec![Key::LeftCommand.into()],
+                    options: KeyOptions::default(),
+                },
+                SimpleRemap {
+                    from: Key::V.into(),
+                    to: vec![Key::LeftCommand.into(), Key::V.into()],
+                    options: KeyOptions::default(),
+                },
+            ],
+        };
+
+        let simple_modifications = remaps_to_simple_modifications(remaps);
+
+        assert_eq!(simple_modifications.len(), 2);
+        assert_eq!(simple_modifications[0].from.key_code, Key::CapsLock);
+        assert_eq!(simple_modifications[0].to.len(), 1);
+        assert_eq!(simple_modifications[0].to[0].key_code, Key::LeftCommand);
+
+        assert_eq!(simple_modifications[1].from.key_code, Key::V);
+        assert_eq!(simple_modifications[1].to.len(), 2);
+        assert_eq!(simple_modifications[1].to[0].key_code, Key::LeftCommand);
+        assert_eq!(simple_modifications[1].to[1].key_code, Key::V);
+    }
+
+    #[test]
+    fn test_key_options_to_event_options() {
+        let options = KeyOptions {
+            repeat: Some(false),
+            lazy: Some(true),
+            halt: None,
+            hold_down_milliseconds: Some(50),
+        };
+        let to: ToKeyMapping = LayerRemap {
+            to: vec![Key::LeftCommand.into()],
+            options: options.clone(),
+        }
+        .into();
+        let simple = remap_to_simple_modification(SimpleRemap {
+            from: Key::CapsLock.into(),
+            to: vec![Key::Escape.into()],
+            options,
+        });
+
+        assert_eq!(
+            serde_json::to_value(to).unwrap(),
+            serde_json::json!({
+                "key_code": "left_command",
+                "modifiers": [],
+                "repeat": false,
+                "lazy": true,
+                "hold_down_milliseconds": 50
+            })
+        );
+        assert_eq!(
+            serde_json::to_value(simple).unwrap(),
+            serde_json::json!({
+                "from": { "key_code": "caps_lock" },
+                "to": [{ "key_code": "escape", "repeat": false, "lazy": true, "hold_down_milliseconds": 50 }]
+            })
+        );
+    }
+
+    #[test]
+    fn test_layer_creates_rule() {
+        let name = String::from("layer1");
+        let keys = KeyCombo::from(Key::LeftCommand);
+
+        let layer = Layer {
+            name: name.clone(),
+            keys: keys.clone(),
+            settings: LayerSettings::default(),
+        };
+
+        let expected_rule = Rule::set_environment(name, keys.into());
+        let rule = layer_to_rule(layer);
+
+        assert_eq!(rule, expected_rule);
+        assert_eq!(
+            rule.manipulators.first().unwrap().from.key_code,
+            Some(Key::LeftCommand.into())
+        );
+        assert_eq!(
+            rule.manipulators.clone().first().unwrap().from.modifiers,
+            None
+        );
+        assert_eq!(rule.description, Some("Change to layer1".to_string()));
+        assert_eq!(rule.manipulators.first().unwrap().conditions, Some(vec![]));
+    }
+
+    #[test]
+    fn test_layer_with_two_keys_creates_rule() {
+        let name = String::from("layer1");
+        let keys = KeyCombo::from(vec![Key::LeftCommand.into(), Key::V.into()]);
+
+        let layer = Layer {
+            name: name.clone(),
+            keys: keys.clone(),
+            settings: LayerSettings::default(),
+        };
+
+        let expected_rule = Rule::set_environment(name, keys.into());
+        let rule = layer_to_rule(layer);
+
+        assert_eq!(rule, expected_rule);
+        assert_eq!(
+            rule.manipulators.first().unwrap().from.key_code,
+            Some(Key::V.into())
+        );
+        assert_eq!(
+            rule.manipulators.first().unwrap().from.modifiers,
+            Some(Modifiers {
+                mandatory: Some(vec![Key::LeftCommand]),
+                optional: None,
+            })
+        );
+    }
+
+    #[test]
+    fn test_layer_assignment_to_remap() {
+        let layer_assignment = LayerAssignment {
+            next_layer: Some(BASE_LAYER.to_string()),
+            ..assignment(
+                Key::H,
+                Action::LayerRemap(LayerRemap {
+                    to: vec![Key::Escape.into(), Key::LeftShift.into()],
+                    options: KeyOptions::default(),
+                }),
+            )
         };
 
         let expected = Rule {
-            description: Some("Remap h to escape".to_string()),
+            description: Some("Remap h to left_shift+escape".to_string()),
             enabled: true,
             manipulators: vec![Manipulator {
                 conditions: Some(vec![Condition {
                     name: "layer1".to_string(),
                     condition_type: "variable_if".into(),
-                    value: 1,
+                    value: 1.into(),
                 }]),
-                from: FromKeyMapping {
-                    key_code: Key::H,
-                    modifiers: None,
-                },
+                from: Key::H.into(),
                 to: Some(vec![ManipulationTarget::KeyMapping(ToKeyMapping {
-                    key_code: Key::Escape,
+                    key_code: Key::Escape.into(),
                     modifiers: vec![Key::LeftShift],
+                    options: ToEventOptions::default(),
                 })]),
                 manipulator_type: "basic".into(),
                 to_if_alone: None,
+                to_if_held_down: None,
                 to_after_key_up: None,
+                parameters: None,
                 to_delayed_action: Some(DelayedAction {
-                    to_if_invoked: vec![SetVariable::new("layer1".to_string(), 0)],
+                    to_if_invoked: vec![ManipulationTarget::set_inactive("layer1".to_string())],
                     to_if_canceled: vec![],
                 }),
             }],
         };
 
-        let rule = layer_assignment_to_rule(layer_assignment);
+        let rule =
+            layer_assignment_to_rule(layer_assignment, &KeyboardLayout::default(), &[]).unwrap();
 
-        assert_eq!(rule, expected);
+        assert_eq!(rule, Some(expected));
     }
     #[test]
     fn test_layer_assignment_to_command() {
         let base_layer = Layer {
             name: BASE_LAYER.to_string(),
-            keys: vec![],
+            keys: KeyCombo::default(),
+            settings: LayerSettings::default(),
         };
         let layer_assignment = LayerAssignment {
-            layer: Layer {
-                name: "layer1".to_string(),
-                keys: vec![Key::LeftCommand],
-            },
-            key: Key::H,
-            action: Action::Command(Command {
-                value: String::from("open -a Terminal"),
-            }),
             next_layer: Some(base_layer.name),
-            description: None,
+            ..assignment(
+                Key::H,
+                Action::Command(Command {
+                    value: String::from("open -a Terminal"),
+                }),
+            )
         };
 
         let expected = Rule {
@@ -311,43 +1513,37 @@ mod tests {
                 conditions: Some(vec![Condition {
                     name: "layer1".to_string(),
                     condition_type: "variable_if".into(),
-                    value: 1,
+                    value: 1.into(),
                 }]),
-                from: FromKeyMapping {
-                    key_code: Key::H,
-                    modifiers: None,
-                },
+                from: Key::H.into(),
                 to: Some(vec![ManipulationTarget::ShellCommand(ShellCommand {
                     shell_command: "open -a Terminal".to_string(),
                 })]),
                 manipulator_type: "basic".into(),
                 to_if_alone: None,
+                to_if_held_down: None,
                 to_after_key_up: None,
+                parameters: None,
                 to_delayed_action: Some(DelayedAction {
                     to_if_canceled: vec![],
-                    to_if_invoked: vec![SetVariable::new("layer1".to_string(), 0)],
+                    to_if_invoked: vec![ManipulationTarget::set_inactive("layer1".to_string())],
                 }),
             }],
         };
 
-        let rule = layer_assignment_to_rule(layer_assignment);
+        let rule =
+            layer_assignment_to_rule(layer_assignment, &KeyboardLayout::default(), &[]).unwrap();
 
-        assert_eq!(rule, expected);
+        assert_eq!(rule, Some(expected));
     }
     #[test]
     fn test_layer_assignment_to_layer_change() {
-        let layer_assignment = LayerAssignment {
-            layer: Layer {
-                name: "layer1".to_string(),
-                keys: vec![Key::LeftCommand],
-            },
-            key: Key::H,
-            action: Action::LayerShift(LayerShift {
+        let layer_assignment = assignment(
+            Key::H,
+            Action::LayerShift(LayerShift {
                 move_layer: "layer2".into(),
             }),
-            next_layer: None,
-            description: None,
-        };
+        );
 
         let expected = Rule {
             description: Some("Switch to layer2".to_string()),
@@ -356,25 +1552,936 @@ mod tests {
                 conditions: Some(vec![Condition {
                     name: "layer1".to_string(),
                     condition_type: "variable_if".into(),
-                    value: 1,
+                    value: 1.into(),
                 }]),
-                from: FromKeyMapping {
-                    key_code: Key::H,
-                    modifiers: None,
-                },
+                from: Key::H.into(),
                 to: Some(vec![
                     ManipulationTarget::SetVariable(SetVariable::new("layer2".to_string(), 1)),
                     ManipulationTarget::SetVariable(SetVariable::new("layer1".to_string(), 0)),
                 ]),
                 manipulator_type: "basic".into(),
                 to_if_alone: None,
+                to_if_held_down: None,
                 to_after_key_up: None,
+                parameters: None,
                 to_delayed_action: None,
             }],
         };
 
-        let rule = layer_assignment_to_rule(layer_assignment);
+        let rule =
+            layer_assignment_to_rule(layer_assignment, &KeyboardLayout::default(), &[]).unwrap();
+
+        assert_eq!(rule, Some(expected));
+    }
+
+    #[test]
+    fn test_unmapped_keys_exit_layer() {
+        let layer = Layer {
+            name: "layer1".to_string(),
+            keys: Key::LeftCommand.into(),
+            settings: LayerSettings {
+                unmapped: UnmappedKeys::ExitAndPassthrough,
+                ..Default::default()
+            },
+        };
+        let assignments = LayerAssignments {
+            assignments: vec![LayerAssignment {
+                layer: layer.clone(),
+                key: Key::H.into(),
+                action: Action::LayerRemap(LayerRemap {
+                    to: vec![Key::LeftArrow.into()],
+                    options: KeyOptions::default(),
+                }),
+                next_layer: None,
+                description: None,
+                pass_modifiers: false,
+            }],
+        };
+
+        let rule = unmapped_to_rule(layer, &assignments);
+
+        assert_eq!(
+            rule.description,
+            Some("Exit layer1 on unmapped keys".to_string())
+        );
+        assert!(rule
+            .manipulators
+            .iter()
+            .all(|m| m.from.key_code != Some(Key::H.into())
+                && !m.from.key_code.as_ref().unwrap().is_modifier()));
+
+        let manipulator = rule
+            .manipulators
+            .iter()
+            .find(|m| m.from.key_code == Some(Key::J.into()))
+            .unwrap();
+        assert_eq!(
+            manipulator.conditions,
+            Some(vec![Condition::active("layer1".to_string())])
+        );
+        assert_eq!(manipulator.from.modifiers, Some(Modifiers::any()));
+        assert_eq!(
+            manipulator.to,
+            Some(vec![
+                ManipulationTarget::set_inactive("layer1".to_string()),
+                ManipulationTarget::KeyMapping(ToKeyMapping {
+                    key_code: Key::J.into(),
+                    modifiers: vec![],
+                    options: ToEventOptions::default(),
+                }),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_unmapped_rule_comes_before_lower_layers() -> Result<()> {
+        let toml_str = r#"
+            [simple_remaps]
+
+            [layers]
+            layer1 = "f13"
+            layer2 = "f14"
+
+            [baselayer]
+            j = { remap = "escape" }
+
+            [layer1]
+            priority = 1
+            unmapped = "exit_and_passthrough"
+            h = { remap = "left_arrow" }
+
+            [layer2]
+            k = { remap = "down_arrow" }
+            "#;
+        let configuration = Configuration::from_toml(&toml_str.parse()?)?;
+
+        let config = convert_configuration(&configuration)?;
+        let descriptions: Vec<String> = config.profiles[0]
+            .complex_modifications
+            .rules
+            .clone()
+            .unwrap()
+            .into_iter()
+            .filter_map(|r| r.description)
+            .filter(|d| d.starts_with("Remap") || d.starts_with("Exit"))
+            .collect();
+
+        assert_eq!(
+            descriptions,
+            vec![
+                "Remap h to left_arrow",
+                "Exit layer1 on unmapped keys",
+                "Remap k to down_arrow",
+                "Remap j to escape",
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_layer_precedence() {
+        let layer = |name: &str, priority: i32| Layer {
+            name: name.to_string(),
+            keys: Key::F13.into(),
+            settings: LayerSettings {
+                priority,
+                ..Default::default()
+            },
+        };
+        let assignment = |layer: &Layer, key: Key, action: Action| LayerAssignment {
+            layer: layer.clone(),
+            key: key.into(),
+            action,
+            next_layer: None,
+            description: None,
+            pass_modifiers: false,
+        };
+        let remap = |key: Key| {
+            Action::LayerRemap(LayerRemap {
+                to: vec![key.into()],
+                options: KeyOptions::default(),
+            })
+        };
+
+        let low = layer("low", 0);
+        let high = layer("high", 1);
+        let top = layer("top", 2);
+        let configuration = Configuration {
+            settings: Settings::default(),
+            simple_remaps: SimpleRemaps { remaps: vec![] },
+            layers: Layers {
+                layers: vec![low.clone(), high.clone(), top.clone()],
+            },
+            layer_assignments: LayerAssignments {
+                assignments: vec![
+                    assignment(&low, Key::H, remap(Key::LeftArrow)),
+                    assignment(&high, Key::H, remap(Key::Home)),
+                    assignment(&top, Key::H, Action::Transparent),
+                ],
+            },
+            home_row_mods: HomeRowMods::default(),
+        };
+
+        let config = convert_configuration(&configuration).unwrap();
+        let rules = config.profiles[0]
+            .complex_modifications
+            .rules
+            .clone()
+            .unwrap();
+        let binding_rules: Vec<&Rule> = rules
+            .iter()
+            .filter(|r| r.manipulators[0].from.key_code == Some(Key::H.into()))
+            .collect();
+
+        assert_eq!(binding_rules.len(), 2);
+        assert_eq!(
+            binding_rules[0].manipulators[0].conditions,
+            Some(vec![Condition::active("high".to_string())])
+        );
+        assert_eq!(
+            binding_rules[1].manipulators[0].conditions,
+            Some(vec![
+                Condition::active("low".to_string()),
+                Condition::inactive("high".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_bindings_with_modifiers_come_first() -> Result<()> {
+        let toml_str = r#"
+            [simple_remaps]
+
+            [layers]
+            layer1 = "f13"
+
+            [baselayer]
+
+            [layer1]
+            h = { remap = "left_arrow" }
+            "left_shift+h" = { remap = "home" }
+            "#;
+        let configuration = Configuration::from_toml(&toml_str.parse()?)?;
+
+        let config = convert_configuration(&configuration)?;
+        let rules = config.profiles[0]
+            .complex_modifications
+            .rules
+            .clone()
+            .unwrap();
+        let binding_rules: Vec<&Rule> = rules
+            .iter()
+            .filter(|r| r.manipulators[0].from.key_code == Some(Key::H.into()))
+            .collect();
+
+        assert_eq!(binding_rules.len(), 2);
+        assert_eq!(
+            serde_json::to_value(&binding_rules[0].manipulators[0].from)?,
+            serde_json::json!({
+                "key_code": "h",
+                "modifiers": { "mandatory": ["left_shift"] }
+            })
+        );
+        assert_eq!(binding_rules[1].manipulators[0].from.modifiers, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_simultaneous_bindings_come_first() -> Result<()> {
+        let toml_str = r#"
+            [simple_remaps]
+
+            [layers]
+            layer1 = "f13"
+
+            [baselayer]
+
+            [layer1]
+            j = { remap = "down_arrow" }
+            "left_shift+j" = { remap = "page_down" }
+            "j&k" = { remap = "escape" }
+            "#;
+        let configuration = Configuration::from_toml(&toml_str.parse()?)?;
+
+        let config = convert_configuration(&configuration)?;
+        let froms: Vec<serde_json::Value> = config.profiles[0]
+            .complex_modifications
+            .rules
+            .clone()
+            .unwrap()
+            .into_iter()
+            .filter(|r| {
+                r.description
+                    .as_ref()
+                    .is_some_and(|d| d.starts_with("Remap"))
+            })
+            .map(|r| serde_json::to_value(&r.manipulators[0].from))
+            .collect::<Result<_, _>>()?;
+
+        assert_eq!(
+            froms,
+            vec![
+                serde_json::json!({
+                    "simultaneous": [{ "key_code": "j" }, { "key_code": "k" }]
+                }),
+                serde_json::json!({
+                    "key_code": "j",
+                    "modifiers": { "mandatory": ["left_shift"] }
+                }),
+                serde_json::json!({ "key_code": "j" }),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_pass_modifiers() {
+        let layer_assignment = LayerAssignment {
+            layer: Layer {
+                name: "nav".to_string(),
+                keys: Key::F13.into(),
+                settings: LayerSettings::default(),
+            },
+            pass_modifiers: true,
+            ..assignment(
+                "left_command+h".parse::<KeyCombo>().unwrap(),
+                Action::LayerRemap(LayerRemap {
+                    to: vec![Key::LeftArrow.into()],
+                    options: KeyOptions::default(),
+                }),
+            )
+        };
+
+        let rule = layer_assignment_to_rule(layer_assignment, &KeyboardLayout::default(), &[])
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            serde_json::to_value(&rule.manipulators[0].from).unwrap(),
+            serde_json::json!({
+                "key_code": "h",
+                "modifiers": { "mandatory": ["left_command"], "optional": ["any"] }
+            })
+        );
+    }
+
+    #[test]
+    fn test_exclusive_layers() {
+        let layer_assignment = assignment(
+            Key::H,
+            Action::LayerShift(LayerShift {
+                move_layer: "layer2".into(),
+            }),
+        );
+        let layers = vec!["layer1".to_string(), "layer2".to_string()];
+
+        let rule = layer_assignment_to_rule(layer_assignment, &KeyboardLayout::default(), &[])
+            .unwrap()
+            .unwrap()
+            .with_exclusive_layers(LAYER_VARIABLE, &layers);
+
+        let manipulator = rule.manipulators.first().unwrap();
+        assert_eq!(
+            manipulator.conditions,
+            Some(vec![Condition {
+                name: LAYER_VARIABLE.to_string(),
+                condition_type: "variable_if".into(),
+                value: "layer1".to_string().into(),
+            }])
+        );
+        assert_eq!(
+            manipulator.to,
+            Some(vec![ManipulationTarget::SetVariable(SetVariable::new(
+                LAYER_VARIABLE.to_string(),
+                "layer2".to_string()
+            ))])
+        );
+    }
+
+    #[test]
+    fn test_exclusive_layers_return_to_base_layer() {
+        let rule = Rule::exit_and_passthrough("layer1".to_string(), vec![Key::J])
+            .with_exclusive_layers(LAYER_VARIABLE, &["layer1".to_string()]);
+
+        assert_eq!(
+            rule.manipulators.first().unwrap().to,
+            Some(vec![
+                ManipulationTarget::SetVariable(SetVariable::new(
+                    LAYER_VARIABLE.to_string(),
+                    BASE_LAYER.to_string()
+                )),
+                ManipulationTarget::KeyMapping(ToKeyMapping {
+                    key_code: Key::J.into(),
+                    modifiers: vec![],
+                    options: ToEventOptions::default(),
+                }),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_layer_notifications() {
+        let layer_assignment = assignment(
+            Key::H,
+            Action::LayerShift(LayerShift {
+                move_layer: "layer2".into(),
+            }),
+        );
+        let layers = vec!["layer1".to_string(), "layer2".to_string()];
+        let labels = vec![("layer2".to_string(), "NAV".to_string())];
+
+        let rule = layer_assignment_to_rule(layer_assignment, &KeyboardLayout::default(), &[])
+            .unwrap()
+            .unwrap()
+            .with_layer_notifications(NOTIFICATION_ID, &layers, &labels, true);
+
+        assert_eq!(
+            rule.manipulators.first().unwrap().to,
+            Some(vec![
+                ManipulationTarget::set_active("layer2".to_string()),
+                ManipulationTarget::set_inactive("layer1".to_string()),
+                ManipulationTarget::NotificationMessage(NotificationMessage::new(
+                    NOTIFICATION_ID.to_string(),
+                    "NAV".to_string()
+                )),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_layer_notification_gets_cleared() {
+        let labels = vec![("layer1".to_string(), "layer1".to_string())];
+        let rule = Rule::exit_and_passthrough("layer1".to_string(), vec![Key::J])
+            .with_layer_notifications(NOTIFICATION_ID, &["layer1".to_string()], &labels, true);
+
+        assert_eq!(
+            rule.manipulators.first().unwrap().to,
+            Some(vec![
+                ManipulationTarget::set_inactive("layer1".to_string()),
+                ManipulationTarget::KeyMapping(ToKeyMapping {
+                    key_code: Key::J.into(),
+                    modifiers: vec![],
+                    options: ToEventOptions::default(),
+                }),
+                ManipulationTarget::NotificationMessage(NotificationMessage::new(
+                    NOTIFICATION_ID.to_string(),
+                    String::new()
+                )),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_layer_notification_restores_remaining_label() {
+        let layers = vec!["layer1".to_string(), "layer2".to_string()];
+        let labels = vec![
+            ("layer2".to_string(), "NAV".to_string()),
+            ("layer1".to_string(), "layer1".to_string()),
+        ];
+        let rule = Rule::exit_and_passthrough("layer1".to_string(), vec![Key::J])
+            .with_layer_notifications(NOTIFICATION_ID, &layers, &labels, true);
+        let notification = |m: &Manipulator| m.to.as_ref().unwrap().last().cloned();
+
+        assert_eq!(rule.manipulators.len(), 2);
+        assert_eq!(
+            rule.manipulators[0].conditions,
+            Some(vec![
+                Condition::active("layer1".to_string()),
+                Condition::active("layer2".to_string()),
+            ])
+        );
+        assert_eq!(
+            notification(&rule.manipulators[0]),
+            Some(ManipulationTarget::NotificationMessage(
+                NotificationMessage::new(NOTIFICATION_ID.to_string(), "NAV".to_string())
+            ))
+        );
+        assert_eq!(
+            rule.manipulators[1].conditions,
+            Some(vec![Condition::active("layer1".to_string())])
+        );
+        assert_eq!(
+            notification(&rule.manipulators[1]),
+            Some(ManipulationTarget::NotificationMessage(
+                NotificationMessage::new(NOTIFICATION_ID.to_string(), String::new())
+            ))
+        );
+
+        let exclusive = Rule::exit_and_passthrough("layer1".to_string(), vec![Key::J])
+            .with_layer_notifications(NOTIFICATION_ID, &layers, &labels, false);
+        assert_eq!(exclusive.manipulators.len(), 1);
+    }
+
+    #[test]
+    fn test_layer_labels() {
+        let layer = |name: &str, notification: Option<Notification>| Layer {
+            name: name.to_string(),
+            keys: KeyCombo::default(),
+            settings: LayerSettings {
+                notification,
+                ..Default::default()
+            },
+        };
+        let configuration = Configuration {
+            settings: Settings {
+                notifications: true,
+                ..Default::default()
+            },
+            simple_remaps: SimpleRemaps { remaps: vec![] },
+            layers: Layers {
+                layers: vec![
+                    layer(BASE_LAYER, None),
+                    layer("layer1", None),
+                    layer("layer2", Some(Notification::Hidden)),
+                    layer("layer3", Some(Notification::Label("NAV".to_string()))),
+                ],
+            },
+            layer_assignments: LayerAssignments {
+                assignments: vec![],
+            },
+            home_row_mods: HomeRowMods::default(),
+        };
+
+        assert_eq!(
+            layer_labels(&configuration),
+            vec![
+                ("layer1".to_string(), "layer1".to_string()),
+                ("layer3".to_string(), "NAV".to_string()),
+            ]
+        );
+    }
 
-        assert_eq!(rule, expected);
+    #[test]
+    fn test_sublayer_timeout() {
+        let layer = Layer {
+            name: "layer1".to_string(),
+            keys: Key::L.into(),
+            settings: LayerSettings::default(),
+        };
+        let sublayer = Layer {
+            name: "layer1.w".to_string(),
+            keys: KeyCombo::default(),
+            settings: LayerSettings {
+                timeout: Some(1000),
+                ..Default::default()
+            },
+        };
+        let configuration = Configuration {
+            settings: Settings::default(),
+            simple_remaps: SimpleRemaps { remaps: vec![] },
+            layers: Layers {
+                layers: vec![layer.clone(), sublayer],
+            },
+            layer_assignments: LayerAssignments {
+                assignments: vec![LayerAssignment {
+                    layer,
+                    key: Key::W.into(),
+                    action: Action::LayerShift(LayerShift {
+                        move_layer: "layer1.w".to_string(),
+                    }),
+                    next_layer: None,
+                    description: None,
+                    pass_modifiers: false,
+                }],
+            },
+            home_row_mods: HomeRowMods::default(),
+        };
+
+        let config = convert_configuration(&configuration).unwrap();
+        let rules = config.profiles[0]
+            .complex_modifications
+            .rules
+            .clone()
+            .unwrap();
+
+        assert_eq!(rules.len(), 2);
+        let manipulator = &rules[1].manipulators[0];
+        assert_eq!(
+            manipulator.to_delayed_action,
+            Some(DelayedAction {
+                to_if_canceled: vec![],
+                to_if_invoked: vec![ManipulationTarget::set_inactive("layer1.w".to_string())],
+            })
+        );
+        assert_eq!(
+            manipulator.parameters,
+            Some(Parameters {
+                to_delayed_action_delay_milliseconds: Some(1000),
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_base_layer_assignment_to_command() {
+        let layer_assignment = LayerAssignment {
+            layer: Layer {
+                name: BASE_LAYER.to_string(),
+                keys: KeyCombo::default(),
+                settings: LayerSettings::default(),
+            },
+            ..assignment(
+                Key::F5,
+                Action::Command(Command {
+                    value: String::from("open -a Terminal"),
+                }),
+            )
+        };
+
+        let rule = layer_assignment_to_rule(layer_assignment, &KeyboardLayout::default(), &[])
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(rule.manipulators[0].conditions, Some(vec![]));
+    }
+
+    #[test]
+    fn test_base_layer_assignment_to_layer_change() {
+        let layer_assignment = LayerAssignment {
+            layer: Layer {
+                name: BASE_LAYER.to_string(),
+                keys: KeyCombo::default(),
+                settings: LayerSettings::default(),
+            },
+            ..assignment(
+                Key::F5,
+                Action::LayerShift(LayerShift {
+                    move_layer: "layer1".into(),
+                }),
+            )
+        };
+
+        let rule = layer_assignment_to_rule(layer_assignment, &KeyboardLayout::default(), &[])
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(rule.manipulators[0].conditions, Some(vec![]));
+        assert_eq!(
+            rule.manipulators[0].to,
+            Some(vec![ManipulationTarget::set_active("layer1".to_string())])
+        );
+    }
+
+    #[test]
+    fn test_layer_change_to_base_layer() {
+        let rule = Rule::switch_layer(
+            BASE_LAYER.to_string(),
+            "layer1".to_string(),
+            Key::Escape.into(),
+        );
+
+        assert_eq!(
+            rule.manipulators[0].to,
+            Some(vec![ManipulationTarget::set_inactive("layer1".to_string())])
+        );
+    }
+
+    #[test]
+    fn test_reset_rule_comes_first() {
+        let layer = Layer {
+            name: "layer1".to_string(),
+            keys: Key::L.into(),
+            settings: LayerSettings::default(),
+        };
+        let configuration = Configuration {
+            settings: Settings {
+                reset: Some(vec![Key::Escape.into(), Key::LeftControl.into()]),
+                ..Default::default()
+            },
+            simple_remaps: SimpleRemaps { remaps: vec![] },
+            layers: Layers {
+                layers: vec![
+                    Layer {
+                        name: BASE_LAYER.to_string(),
+                        keys: KeyCombo::default(),
+                        settings: LayerSettings::default(),
+                    },
+                    layer.clone(),
+                    Layer {
+                        name: "layer1.w".to_string(),
+                        keys: KeyCombo::default(),
+                        settings: LayerSettings::default(),
+                    },
+                ],
+            },
+            layer_assignments: LayerAssignments {
+                assignments: vec![],
+            },
+            home_row_mods: HomeRowMods::default(),
+        };
+
+        let config = convert_configuration(&configuration).unwrap();
+        let rules = config.profiles[0]
+            .complex_modifications
+            .rules
+            .clone()
+            .unwrap();
+
+        let manipulator = &rules[0].manipulators[0];
+        assert_eq!(manipulator.conditions, Some(vec![]));
+        assert_eq!(manipulator.from, vec![Key::Escape, Key::LeftControl].into());
+        assert_eq!(
+            manipulator.to,
+            Some(vec![
+                ManipulationTarget::set_inactive("layer1".to_string()),
+                ManipulationTarget::set_inactive("layer1.w".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_keys_to_key_mapping_with_leading_modifier() {
+        let mapping: ToKeyMapping = vec![Key::LeftCommand, Key::K].into();
+
+        assert_eq!(mapping.key_code, Key::K);
+        assert_eq!(mapping.modifiers, vec![Key::LeftCommand]);
+    }
+
+    #[test]
+    fn test_layer_assignment_to_macro() {
+        let layer_assignment = assignment(
+            Key::S,
+            Action::Macro(Macro {
+                steps: vec![
+                    MacroStep {
+                        keys: vec![Key::LeftCommand.into(), Key::K.into()],
+                        hold_down_milliseconds: Some(50),
+                    },
+                    MacroStep {
+                        keys: vec![Key::LeftCommand.into(), Key::S.into()],
+                        hold_down_milliseconds: None,
+                    },
+                ],
+            }),
+        );
+
+        let rule = layer_assignment_to_rule(layer_assignment, &KeyboardLayout::default(), &[])
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            rule.description,
+            Some("Remap s to left_command+k, left_command+s".to_string())
+        );
+        assert_eq!(
+            rule.manipulators[0].to,
+            Some(vec![
+                ManipulationTarget::KeyMapping(ToKeyMapping {
+                    key_code: Key::K.into(),
+                    modifiers: vec![Key::LeftCommand],
+                    options: ToEventOptions {
+                        hold_down_milliseconds: Some(50),
+                        ..Default::default()
+                    },
+                }),
+                ManipulationTarget::KeyMapping(ToKeyMapping {
+                    key_code: Key::S.into(),
+                    modifiers: vec![Key::LeftCommand],
+                    options: ToEventOptions::default(),
+                }),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_select_input_source_without_values_fails() {
+        let layer_assignment = assignment(
+            Key::E,
+            Action::SelectInputSource(SelectInputSource {
+                language: None,
+                input_source_id: None,
+            }),
+        );
+
+        assert!(
+            layer_assignment_to_rule(layer_assignment, &KeyboardLayout::default(), &[]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_text_to_key_mappings() -> Result<()> {
+        let mappings = text_to_key_mappings("Hi!\n", &KeyboardLayout::Us)?;
+
+        let keys: Vec<(Key, Vec<Key>)> = mappings
+            .into_iter()
+            .map(|m| (m.key_code.as_key().unwrap().clone(), m.modifiers))
+            .collect();
+        assert_eq!(
+            keys,
+            vec![
+                (Key::H, vec![Key::LeftShift]),
+                (Key::I, vec![]),
+                (Key::Key1, vec![Key::LeftShift]),
+                (Key::ReturnOrEnter, vec![]),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_text_to_key_mappings_with_german_layout() -> Result<()> {
+        let mappings = text_to_key_mappings("Zü@", &KeyboardLayout::De)?;
+
+        let keys: Vec<(Key, Vec<Key>)> = mappings
+            .into_iter()
+            .map(|m| (m.key_code.as_key().unwrap().clone(), m.modifiers))
+            .collect();
+        assert_eq!(
+            keys,
+            vec![
+                (Key::Y, vec![Key::LeftShift]),
+                (Key::OpenBracket, vec![]),
+                (Key::L, vec![Key::LeftOption]),
+            ]
+        );
+        assert!(text_to_key_mappings("^", &KeyboardLayout::De).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_text_with_untypeable_character_fails() {
+        assert!(text_to_key_mappings("Grüße", &KeyboardLayout::Us).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "software-function")]
+    fn test_open_app_to_software_function() {
+        let target: ManipulationTarget =
+            OpenApp::BundleId("com.tinyspeck.slackmacgap".to_string()).into();
+
+        assert_eq!(
+            target,
+            ManipulationTarget::SoftwareFunction(SoftwareFunction {
+                software_function: SoftwareFunctionValues::OpenApplication(OpenApplication {
+                    bundle_identifier: Some("com.tinyspeck.slackmacgap".to_string()),
+                    file_path: None,
+                }),
+            })
+        );
+    }
+
+    #[test]
+    fn test_open_app_by_name_to_shell_command() {
+        let target: ManipulationTarget = OpenApp::Name("Brave Browser".to_string()).into();
+
+        assert_eq!(
+            target,
+            ManipulationTarget::ShellCommand(ShellCommand {
+                shell_command: "open -a 'Brave Browser'".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_open_url_to_shell_command() {
+        let url = OpenUrl {
+            url: "https://example.com/?q=it's&page=1".to_string(),
+        };
+
+        assert_eq!(
+            ShellCommand::from(url).shell_command,
+            r"open 'https://example.com/?q=it'\''s&page=1'"
+        );
+    }
+
+    #[test]
+    fn test_select_input_source_to_manipulation_target() {
+        let input_source = SelectInputSource {
+            language: Some("de".to_string()),
+            input_source_id: Some("com.apple.keylayout.German".to_string()),
+        };
+
+        assert_eq!(
+            serde_json::to_value(ManipulationTarget::from(input_source)).unwrap(),
+            serde_json::json!({
+                "select_input_source": {
+                    "language": "^de$",
+                    "input_source_id": r"^com\.apple\.keylayout\.German$"
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn test_sticky_to_manipulation_target() {
+        let layer_assignment = assignment(
+            Key::S,
+            Action::Sticky(Sticky {
+                modifier: Key::LeftShift,
+                mode: StickyModifierMode::Toggle,
+            }),
+        );
+
+        let rule = layer_assignment_to_rule(layer_assignment, &KeyboardLayout::default(), &[])
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            rule.description,
+            Some("Sticky modifier left_shift toggle".to_string())
+        );
+        assert_eq!(
+            serde_json::to_value(&rule.manipulators[0].to).unwrap(),
+            serde_json::json!([{ "sticky_modifier": { "left_shift": "toggle" } }])
+        );
+    }
+
+    #[test]
+    fn test_mouse_to_manipulation_target() {
+        let to_json = |mouse: Mouse| serde_json::to_value(ManipulationTarget::from(mouse)).unwrap();
+
+        assert_eq!(
+            to_json(Mouse::Move {
+                direction: Direction::Left,
+                speed: 1536
+            }),
+            serde_json::json!({ "mouse_key": { "x": -1536 } })
+        );
+        assert_eq!(
+            to_json(Mouse::Scroll {
+                direction: Direction::Up,
+                speed: 32
+            }),
+            serde_json::json!({ "mouse_key": { "vertical_wheel": -32 } })
+        );
+        assert_eq!(
+            to_json(Mouse::Click("button1".to_string())),
+            serde_json::json!({ "pointing_button": "button1" })
+        );
+        assert_eq!(
+            to_json(Mouse::SpeedMultiplier(
+                serde_json::Number::from_f64(2.5).unwrap()
+            )),
+            serde_json::json!({ "mouse_key": { "speed_multiplier": 2.5 } })
+        );
+    }
+
+    #[test]
+    fn test_applescript_to_shell_command() {
+        let inline = AppleScript::Inline(
+            "tell application \"Finder\"\n  display dialog \"It's done\"\nend tell".to_string(),
+        );
+        let file = AppleScript::File("~/My Scripts/focus.scpt".to_string());
+
+        assert_eq!(
+            ShellCommand::from(inline).shell_command,
+            r#"osascript -e 'tell application "Finder"' -e '  display dialog "It'\''s done"' -e 'end tell'"#
+        );
+        assert_eq!(
+            ShellCommand::from(file).shell_command,
+            "osascript ~/'My Scripts/focus.scpt'"
+        );
+    }
+
+    #[test]
+    fn test_shortcut_to_shell_command() {
+        let shortcut = Shortcut {
+            name: "Start Focus".to_string(),
+        };
+
+        assert_eq!(
+            ShellCommand::from(shortcut).shell_command,
+            "shortcuts run 'Start Focus'"
+        );
     }
 }
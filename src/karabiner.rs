@@ -1,7 +1,9 @@
+use std::collections::HashMap;
+
 use serde::{Deserialize, Serialize};
 
-use crate::configuration::BASE_LAYER;
-use crate::keys::Key;
+use crate::configuration::{HomeRowMod, BASE_LAYER};
+use crate::keys::{Key, KeyCode, KeyDownOrder, StickyModifierMode, SIMULTANEOUS_SEPARATOR};
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct KarabinerConfig {
@@ -46,7 +48,7 @@ impl Rule {
         target_layer: Option<String>,
     ) -> Self {
         Self {
-            description: Some(format!("Remap {} to {}", from.key_code, to.key_code)),
+            description: Some(format!("Remap {} to {}", from, to)),
             enabled: true,
             manipulators: vec![Manipulator::set_keymapping_in_layer(
                 layer,
@@ -57,6 +59,88 @@ impl Rule {
         }
     }
 
+    pub fn set_keymappings_in_layer(
+        layer: String,
+        from: FromKeyMapping,
+        to: Vec<ToKeyMapping>,
+        target_layer: Option<String>,
+    ) -> Self {
+        let keys: Vec<String> = to.iter().map(ToKeyMapping::to_string).collect();
+        Self {
+            description: Some(format!("Remap {} to {}", from, keys.join(", "))),
+            enabled: true,
+            manipulators: vec![Manipulator::set_targets_in_layer(
+                layer,
+                from,
+                to.into_iter().map(ManipulationTarget::KeyMapping).collect(),
+                target_layer,
+            )],
+        }
+    }
+
+    pub fn type_text_in_layer(
+        layer: String,
+        from: FromKeyMapping,
+        to: Vec<ToKeyMapping>,
+        text: String,
+        target_layer: Option<String>,
+    ) -> Self {
+        Self {
+            description: Some(format!("Type {}", text.escape_default())),
+            enabled: true,
+            manipulators: vec![Manipulator::set_targets_in_layer(
+                layer,
+                from,
+                to.into_iter().map(ManipulationTarget::KeyMapping).collect(),
+                target_layer,
+            )],
+        }
+    }
+
+    pub fn set_targets_in_layer(
+        description: String,
+        layer: String,
+        from: FromKeyMapping,
+        to: Vec<ManipulationTarget>,
+        target_layer: Option<String>,
+    ) -> Self {
+        Self {
+            description: Some(description),
+            enabled: true,
+            manipulators: vec![Manipulator::set_targets_in_layer(
+                layer,
+                from,
+                to,
+                target_layer,
+            )],
+        }
+    }
+
+    pub fn tap_hold_in_layer(
+        layer: String,
+        from: FromKeyMapping,
+        tap: ToKeyMapping,
+        hold: ToKeyMapping,
+        timeout: u32,
+        target_layer: Option<String>,
+    ) -> Self {
+        Self {
+            description: Some(format!(
+                "Tap {} to send {}, hold to send {}",
+                from, tap.key_code, hold.key_code
+            )),
+            enabled: true,
+            manipulators: vec![Manipulator::tap_hold_in_layer(
+                layer,
+                from,
+                tap,
+                hold,
+                timeout,
+                target_layer,
+            )],
+        }
+    }
+
     pub fn set_command_in_layer(
         layer: String,
         from: FromKeyMapping,
@@ -82,6 +166,285 @@ impl Rule {
             manipulators: vec![Manipulator::switch_layer(target_layer, source_layer, from)],
         }
     }
+
+    pub fn reset_layers(layers: Vec<String>, from: FromKeyMapping) -> Self {
+        Self {
+            description: Some("Reset to base layer".to_string()),
+            enabled: true,
+            manipulators: vec![Manipulator::reset_layers(layers, from)],
+        }
+    }
+
+    /// Adds the conditions to all manipulators of the rule.
+    pub fn with_conditions(mut self, conditions: Vec<Condition>) -> Self {
+        for manipulator in self.manipulators.iter_mut() {
+            manipulator
+                .conditions
+                .get_or_insert_with(Vec::new)
+                .extend(conditions.iter().cloned());
+        }
+        self
+    }
+
+    /// Leaves the layer which gets activated by the rule again if no other key is pressed within
+    /// the timeout.
+    pub fn with_timeout(mut self, layer: String, milliseconds: u32) -> Self {
+        for manipulator in self.manipulators.iter_mut() {
+            manipulator.to_delayed_action = Some(DelayedAction {
+                to_if_canceled: vec![],
+                to_if_invoked: vec![ManipulationTarget::set_inactive(layer.clone())],
+            });
+            manipulator
+                .parameters
+                .get_or_insert_with(Parameters::default)
+                .to_delayed_action_delay_milliseconds = Some(milliseconds);
+        }
+        self
+    }
+
+    /// Sets the time within which simultaneous keys have to be pressed, unless it is already set.
+    pub fn with_simultaneous_threshold(mut self, milliseconds: u32) -> Self {
+        for manipulator in self
+            .manipulators
+            .iter_mut()
+            .filter(|m| m.from.simultaneous.is_some())
+        {
+            manipulator
+                .parameters
+                .get_or_insert_with(Parameters::default)
+                .simultaneous_threshold_milliseconds
+                .get_or_insert(milliseconds);
+        }
+        self
+    }
+
+    /// Lets the manipulators trigger while other modifiers are held and passes these on.
+    pub fn with_optional_modifiers(mut self) -> Self {
+        for manipulator in self.manipulators.iter_mut() {
+            manipulator
+                .from
+                .modifiers
+                .get_or_insert_with(Modifiers::default)
+                .optional = Some(vec![Key::Any]);
+        }
+        self
+    }
+
+    /// Stores the active layer in a single variable instead of one variable per layer, which makes
+    /// the layers mutually exclusive.
+    pub fn with_exclusive_layers(mut self, variable: &str, layers: &[String]) -> Self {
+        for manipulator in self.manipulators.iter_mut() {
+            manipulator.conditions = manipulator.conditions.take().map(|conditions| {
+                conditions
+                    .into_iter()
+                    .map(|c| match layers.contains(&c.name) {
+                        true => c.exclusive(variable),
+                        false => c,
+                    })
+                    .collect()
+            });
+            manipulator.map_events(|events| exclusive_layer_events(events, variable, layers));
+        }
+        self
+    }
+
+    /// Shows a notification with the label of the layer whenever a layer with a label is entered
+    /// and clears it again when the layer is left. Labels are given by precedence, highest first.
+    /// With stacked layers another labeled layer can still be active when a layer is left, so the
+    /// manipulator is repeated for each of these layers to restore its label instead.
+    pub fn with_layer_notifications(
+        mut self,
+        id: &str,
+        layers: &[String],
+        labels: &[(String, String)],
+        stacked_layers: bool,
+    ) -> Self {
+        self.manipulators = self
+            .manipulators
+            .into_iter()
+            .flat_map(|manipulator| {
+                let remaining: Vec<&(String, String)> = match stacked_layers {
+                    true => labels
+                        .iter()
+                        .filter(|(layer, _)| !manipulator.changes_layer(layer))
+                        .collect(),
+                    false => vec![],
+                };
+                let leaves_label = labels
+                    .iter()
+                    .any(|(layer, _)| manipulator.leaves_layer(layer));
+
+                let mut manipulators = vec![];
+                if leaves_label {
+                    for (layer, label) in remaining {
+                        let mut restoring = manipulator.clone();
+                        restoring
+                            .conditions
+                            .get_or_insert_with(Vec::new)
+                            .push(Condition::active(layer.clone()));
+                        restoring.map_events(|events| {
+                            layer_notification_events(events, id, layers, labels, label)
+                        });
+                        manipulators.push(restoring);
+                    }
+                }
+                let mut manipulator = manipulator;
+                manipulator
+                    .map_events(|events| layer_notification_events(events, id, layers, labels, ""));
+                manipulators.push(manipulator);
+                manipulators
+            })
+            .collect();
+        self
+    }
+
+    /// Moves the events of `tap` to `to_if_alone` and sends the events of `hold` once the key has
+    /// been held down for the threshold. A manipulator has only one delayed action, so the delayed
+    /// events of `hold`, such as the timeout of a layer it enters, are added to those of `tap` and
+    /// the delay of `hold` wins.
+    pub fn long_press(tap: Rule, hold: Rule, threshold: u32) -> Self {
+        let mut hold_events: Vec<ManipulationTarget> = vec![];
+        let mut hold_delayed_action: Option<DelayedAction> = None;
+        let mut hold_delay = None;
+        for m in hold.manipulators {
+            hold_events.extend(m.to.unwrap_or_default());
+            if let Some(delayed_action) = m.to_delayed_action {
+                hold_delayed_action = Some(delayed_action);
+                hold_delay = m
+                    .parameters
+                    .and_then(|p| p.to_delayed_action_delay_milliseconds);
+            }
+        }
+        let manipulators = tap
+            .manipulators
+            .into_iter()
+            .map(|mut m| {
+                m.to_if_alone = m.to.replace(vec![]);
+                m.to_if_held_down = Some(hold_events.clone());
+                if let Some(hold_delayed_action) = &hold_delayed_action {
+                    m.to_delayed_action = Some(match m.to_delayed_action {
+                        Some(delayed_action) => delayed_action.merge(hold_delayed_action),
+                        None => hold_delayed_action.clone(),
+                    });
+                }
+                let parameters = m.parameters.get_or_insert_with(Parameters::default);
+                parameters.to_if_alone_timeout_milliseconds = Some(threshold);
+                parameters.to_if_held_down_threshold_milliseconds = Some(threshold);
+                if hold_delay.is_some() {
+                    parameters.to_delayed_action_delay_milliseconds = hold_delay;
+                }
+                m
+            })
+            .collect();
+
+        Self {
+            description: Some(format!(
+                "{}, hold: {}",
+                tap.description.unwrap_or_default(),
+                hold.description.unwrap_or_default()
+            )),
+            enabled: true,
+            manipulators,
+        }
+    }
+
+    /// Runs the manipulators of `double` instead of `single` when the key is pressed again before
+    /// the timeout resets the variable. A manipulator has only one delayed action, so if `single`
+    /// already has one, such as a layer change, the variable is reset by it and it keeps its own
+    /// delay instead of the timeout.
+    pub fn double_tap(single: Rule, double: Rule, variable: String, timeout: u32) -> Self {
+        let double_manipulators = double.manipulators.into_iter().map(|mut m| {
+            m.conditions
+                .get_or_insert_with(Vec::new)
+                .push(Condition::active(variable.clone()));
+            m.to.get_or_insert_with(Vec::new)
+                .insert(0, ManipulationTarget::set_inactive(variable.clone()));
+            m
+        });
+        let single_manipulators = single.manipulators.into_iter().map(|mut m| {
+            m.to.get_or_insert_with(Vec::new)
+                .insert(0, ManipulationTarget::set_active(variable.clone()));
+            if m.to_delayed_action.is_none() {
+                m.parameters
+                    .get_or_insert_with(Parameters::default)
+                    .to_delayed_action_delay_milliseconds = Some(timeout);
+            }
+            let delayed_action = m.to_delayed_action.get_or_insert_with(|| DelayedAction {
+                to_if_canceled: vec![],
+                to_if_invoked: vec![],
+            });
+            for events in [
+                &mut delayed_action.to_if_invoked,
+                &mut delayed_action.to_if_canceled,
+            ] {
+                events.insert(0, ManipulationTarget::set_inactive(variable.clone()));
+            }
+            m
+        });
+
+        Self {
+            description: Some(format!(
+                "{}, double tap: {}",
+                single.description.unwrap_or_default(),
+                double.description.unwrap_or_default()
+            )),
+            enabled: true,
+            manipulators: double_manipulators.chain(single_manipulators).collect(),
+        }
+    }
+
+    /// Home row keys type while the typing streak is active and otherwise act as modifier once
+    /// they are held down longer than their hold timeout, which defaults to `hold_timeout`.
+    pub fn home_row_mods(
+        keys: &[HomeRowMod],
+        hold_timeout: u32,
+        streak_variable: &str,
+        streak_timeout: u32,
+    ) -> Self {
+        Self {
+            description: Some("Home row modifiers".to_string()),
+            enabled: true,
+            manipulators: keys
+                .iter()
+                .flat_map(|m| {
+                    let mut typing =
+                        Manipulator::typing_streak(m.key.clone(), streak_variable, streak_timeout);
+                    typing.conditions = Some(vec![Condition::active(streak_variable.to_string())]);
+                    [
+                        typing,
+                        Manipulator::home_row_mod(
+                            m.key.clone(),
+                            m.modifier.clone(),
+                            m.hold_timeout.unwrap_or(hold_timeout),
+                        ),
+                    ]
+                })
+                .collect(),
+        }
+    }
+
+    /// Starts or extends the typing streak whenever one of the keys is typed.
+    pub fn typing_streak(keys: Vec<Key>, variable: &str, timeout: u32) -> Self {
+        Self {
+            description: Some("Track typing streak".to_string()),
+            enabled: true,
+            manipulators: keys
+                .into_iter()
+                .map(|key| Manipulator::typing_streak(key, variable, timeout))
+                .collect(),
+        }
+    }
+
+    pub fn exit_and_passthrough(layer: String, keys: Vec<Key>) -> Self {
+        Self {
+            description: Some(format!("Exit {} on unmapped keys", layer)),
+            enabled: true,
+            manipulators: keys
+                .into_iter()
+                .map(|key| Manipulator::exit_and_passthrough(layer.clone(), key))
+                .collect(),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
@@ -94,11 +457,39 @@ pub struct Manipulator {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub to_after_key_up: Option<Vec<ManipulationTarget>>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub to_if_alone: Option<Vec<FromKeyMapping>>,
+    pub to_if_alone: Option<Vec<ManipulationTarget>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to_if_held_down: Option<Vec<ManipulationTarget>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parameters: Option<Parameters>,
     #[serde(rename = "type")]
     pub manipulator_type: String,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct Parameters {
+    #[serde(
+        rename = "basic.to_delayed_action_delay_milliseconds",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub to_delayed_action_delay_milliseconds: Option<u32>,
+    #[serde(
+        rename = "basic.to_if_alone_timeout_milliseconds",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub to_if_alone_timeout_milliseconds: Option<u32>,
+    #[serde(
+        rename = "basic.to_if_held_down_threshold_milliseconds",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub to_if_held_down_threshold_milliseconds: Option<u32>,
+    #[serde(
+        rename = "basic.simultaneous_threshold_milliseconds",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub simultaneous_threshold_milliseconds: Option<u32>,
+}
+
 impl Manipulator {
     pub fn set_environment(name: String, from: FromKeyMapping) -> Self {
         Manipulator {
@@ -108,6 +499,8 @@ impl Manipulator {
             to_delayed_action: None,
             to_after_key_up: None,
             to_if_alone: None,
+            to_if_held_down: None,
+            parameters: None,
             manipulator_type: "basic".to_string(),
         }
     }
@@ -118,75 +511,367 @@ impl Manipulator {
         to: ToKeyMapping,
         target_layer: Option<String>,
     ) -> Self {
-        let conditions = if Self::is_base_layer(&layer) {
-            Some(vec![])
-        } else {
-            Some(vec![Condition::active(layer.clone())])
-        };
+        Self::set_targets_in_layer(
+            layer,
+            from,
+            vec![ManipulationTarget::KeyMapping(to)],
+            target_layer,
+        )
+    }
+
+    pub fn set_command_in_layer(
+        layer: String,
+        from: FromKeyMapping,
+        to: ShellCommand,
+        target_layer: Option<String>,
+    ) -> Self {
+        Self::set_targets_in_layer(
+            layer,
+            from,
+            vec![ManipulationTarget::ShellCommand(to)],
+            target_layer,
+        )
+    }
+
+    pub fn set_targets_in_layer(
+        layer: String,
+        from: FromKeyMapping,
+        to: Vec<ManipulationTarget>,
+        target_layer: Option<String>,
+    ) -> Self {
         Manipulator {
-            conditions,
+            conditions: Self::layer_conditions(&layer),
             from,
-            to: Some(vec![ManipulationTarget::KeyMapping(to)]),
+            to: Some(to),
             to_delayed_action: set_target_layer(target_layer, layer),
             to_after_key_up: None,
             to_if_alone: None,
+            to_if_held_down: None,
+            parameters: None,
             manipulator_type: "basic".to_string(),
         }
     }
 
-    pub fn set_command_in_layer(
+    /// Modifiers which are held are sent right away, so that they apply to other keys pressed in
+    /// the meantime. Other keys are only sent once the key has been held down long enough. Both
+    /// timeouts are the same, otherwise karabiner's defaults send tap and hold for some presses.
+    pub fn tap_hold_in_layer(
         layer: String,
         from: FromKeyMapping,
-        to: ShellCommand,
+        tap: ToKeyMapping,
+        hold: ToKeyMapping,
+        timeout: u32,
         target_layer: Option<String>,
     ) -> Self {
+        let tap = vec![ManipulationTarget::KeyMapping(tap)];
+        let hold_is_modifier = hold.key_code.is_modifier();
+        let hold = vec![ManipulationTarget::KeyMapping(hold)];
+        let (to, to_if_held_down) = match hold_is_modifier {
+            true => (hold, None),
+            false => (vec![], Some(hold)),
+        };
+        let parameters = Parameters {
+            to_if_alone_timeout_milliseconds: Some(timeout),
+            to_if_held_down_threshold_milliseconds: Some(timeout),
+            ..Default::default()
+        };
+
         Manipulator {
-            conditions: Some(vec![Condition::active(layer.clone())]),
+            conditions: Self::layer_conditions(&layer),
             from,
-            to: Some(vec![ManipulationTarget::ShellCommand(to)]),
+            to: Some(to),
             to_delayed_action: set_target_layer(target_layer, layer),
             to_after_key_up: None,
-            to_if_alone: None,
+            to_if_alone: Some(tap),
+            to_if_held_down,
+            parameters: Some(parameters),
             manipulator_type: "basic".to_string(),
         }
     }
+
     fn switch_layer(
         target_layer: String,
         source_layer: String,
         from: FromKeyMapping,
     ) -> Manipulator {
         Manipulator {
-            conditions: Some(vec![Condition::active(source_layer.clone())]),
+            conditions: Self::layer_conditions(&source_layer),
             from,
+            to: Some(ManipulationTarget::change_layer(target_layer, source_layer)),
+            to_delayed_action: None,
+            to_after_key_up: None,
+            to_if_alone: None,
+            to_if_held_down: None,
+            parameters: None,
+            manipulator_type: "basic".to_string(),
+        }
+    }
+
+    fn reset_layers(layers: Vec<String>, from: FromKeyMapping) -> Manipulator {
+        Manipulator {
+            conditions: Some(vec![]),
+            from,
+            to: Some(
+                layers
+                    .into_iter()
+                    .map(ManipulationTarget::set_inactive)
+                    .collect(),
+            ),
+            to_delayed_action: None,
+            to_after_key_up: None,
+            to_if_alone: None,
+            to_if_held_down: None,
+            parameters: None,
+            manipulator_type: "basic".to_string(),
+        }
+    }
+
+    fn exit_and_passthrough(layer: String, key: Key) -> Manipulator {
+        Manipulator {
+            conditions: Some(vec![Condition::active(layer.clone())]),
+            from: FromKeyMapping::with_any_modifiers(key.clone()),
             to: Some(vec![
-                ManipulationTarget::set_active(target_layer),
-                ManipulationTarget::set_inactive(source_layer),
+                ManipulationTarget::set_inactive(layer),
+                ManipulationTarget::KeyMapping(ToKeyMapping {
+                    key_code: key.into(),
+                    modifiers: vec![],
+                    options: ToEventOptions::default(),
+                }),
             ]),
             to_delayed_action: None,
             to_after_key_up: None,
             to_if_alone: None,
+            to_if_held_down: None,
+            parameters: None,
+            manipulator_type: "basic".to_string(),
+        }
+    }
+
+    /// Types the key and keeps the streak variable set until no key has been typed for the timeout.
+    /// Any other key pressed in the meantime, such as return or an arrow key, ends the streak, while
+    /// the next character key sets it again.
+    fn typing_streak(key: Key, variable: &str, timeout: u32) -> Manipulator {
+        Manipulator {
+            conditions: Some(vec![]),
+            from: FromKeyMapping::with_any_modifiers(key.clone()),
+            to: Some(vec![
+                ManipulationTarget::set_active(variable.to_string()),
+                ManipulationTarget::KeyMapping(vec![key].into()),
+            ]),
+            to_delayed_action: Some(DelayedAction {
+                to_if_invoked: vec![ManipulationTarget::set_inactive(variable.to_string())],
+                to_if_canceled: vec![ManipulationTarget::set_inactive(variable.to_string())],
+            }),
+            to_after_key_up: None,
+            to_if_alone: None,
+            to_if_held_down: None,
+            parameters: Some(Parameters {
+                to_delayed_action_delay_milliseconds: Some(timeout),
+                ..Default::default()
+            }),
             manipulator_type: "basic".to_string(),
         }
     }
 
+    /// Sends the key when it is tapped or when another key is pressed before the hold timeout, so
+    /// that rolling over the key while typing does not trigger the modifier. Karabiner cancels the
+    /// pending `to_delayed_action` when an event with `halt` in `to_if_alone` is sent, so a tap only
+    /// types the key once instead of also sending it from `to_if_canceled`.
+    fn home_row_mod(key: Key, modifier: Key, hold_timeout: u32) -> Manipulator {
+        let key_event = |halt| {
+            ManipulationTarget::KeyMapping(ToKeyMapping {
+                options: ToEventOptions {
+                    halt,
+                    ..Default::default()
+                },
+                ..vec![key.clone()].into()
+            })
+        };
+
+        Manipulator {
+            conditions: Some(vec![]),
+            from: FromKeyMapping::with_any_modifiers(key.clone()),
+            to: Some(vec![]),
+            to_delayed_action: Some(DelayedAction {
+                to_if_invoked: vec![],
+                to_if_canceled: vec![key_event(None)],
+            }),
+            to_after_key_up: None,
+            to_if_alone: Some(vec![key_event(Some(true))]),
+            to_if_held_down: Some(vec![ManipulationTarget::KeyMapping(vec![modifier].into())]),
+            parameters: Some(Parameters {
+                to_delayed_action_delay_milliseconds: Some(hold_timeout),
+                to_if_alone_timeout_milliseconds: Some(hold_timeout),
+                to_if_held_down_threshold_milliseconds: Some(hold_timeout),
+                ..Default::default()
+            }),
+            manipulator_type: "basic".to_string(),
+        }
+    }
+
+    /// Events of all lists of the manipulator.
+    fn events(&self) -> impl Iterator<Item = &ManipulationTarget> {
+        let delayed_events = self
+            .to_delayed_action
+            .iter()
+            .flat_map(|d| d.to_if_invoked.iter().chain(&d.to_if_canceled));
+        [
+            &self.to,
+            &self.to_after_key_up,
+            &self.to_if_alone,
+            &self.to_if_held_down,
+        ]
+        .into_iter()
+        .flatten()
+        .flatten()
+        .chain(delayed_events)
+    }
+
+    /// Whether any of the events activates or deactivates the layer.
+    fn changes_layer(&self, layer: &String) -> bool {
+        self.events()
+            .any(|event| layer_change(event, std::slice::from_ref(layer)).is_some())
+    }
+
+    /// Whether any of the events deactivates the layer.
+    fn leaves_layer(&self, layer: &String) -> bool {
+        self.events()
+            .filter_map(|event| layer_change(event, std::slice::from_ref(layer)))
+            .any(|(_, active)| !active)
+    }
+
+    /// Applies the function to every list of events of the manipulator.
+    fn map_events(&mut self, f: impl Fn(Vec<ManipulationTarget>) -> Vec<ManipulationTarget>) {
+        self.to = self.to.take().map(&f);
+        self.to_after_key_up = self.to_after_key_up.take().map(&f);
+        self.to_if_alone = self.to_if_alone.take().map(&f);
+        self.to_if_held_down = self.to_if_held_down.take().map(&f);
+        if let Some(delayed_action) = self.to_delayed_action.as_mut() {
+            delayed_action.to_if_invoked = f(std::mem::take(&mut delayed_action.to_if_invoked));
+            delayed_action.to_if_canceled = f(std::mem::take(&mut delayed_action.to_if_canceled));
+        }
+    }
+
+    /// Conditions under which the bindings of a layer apply. The base layer has no variable and is
+    /// always active.
+    fn layer_conditions(layer: &str) -> Option<Vec<Condition>> {
+        if Self::is_base_layer(layer) {
+            Some(vec![])
+        } else {
+            Some(vec![Condition::active(layer.to_string())])
+        }
+    }
+
     fn is_base_layer(name: &str) -> bool {
         name == BASE_LAYER
     }
 }
 
-fn set_target_layer(target_layer: Option<String>, source_layer: String) -> Option<DelayedAction> {
-    match target_layer {
-        None => None,
-        Some(layer) => Some(DelayedAction::set_layer(layer, source_layer)),
+/// Replaces the layer variables which are set by the events with a single assignment of the
+/// exclusive layer variable. Activating a layer implicitly leaves all other layers, and leaving a
+/// layer without activating another one returns to the base layer.
+fn exclusive_layer_events(
+    events: Vec<ManipulationTarget>,
+    variable: &str,
+    layers: &[String],
+) -> Vec<ManipulationTarget> {
+    let is_layer_event = |event: &ManipulationTarget| match event {
+        ManipulationTarget::SetVariable(v) => layers.contains(&v.set_variable.name),
+        _ => false,
+    };
+    let Some(position) = events.iter().position(is_layer_event) else {
+        return events;
+    };
+
+    let active_layer = events
+        .iter()
+        .rev()
+        .find_map(|event| match event {
+            ManipulationTarget::SetVariable(v)
+                if layers.contains(&v.set_variable.name)
+                    && v.set_variable.value == VariableValue::Integer(1) =>
+            {
+                Some(v.set_variable.name.clone())
+            }
+            _ => None,
+        })
+        .unwrap_or_else(|| BASE_LAYER.to_string());
+
+    let mut events: Vec<ManipulationTarget> = events
+        .into_iter()
+        .filter(|event| !is_layer_event(event))
+        .collect();
+    events.insert(
+        position,
+        ManipulationTarget::SetVariable(SetVariable::new(variable.to_string(), active_layer)),
+    );
+    events
+}
+
+/// Appends a notification for the layer which gets entered by the events, or shows the
+/// `remaining` label if a layer with a label is left without entering another one.
+fn layer_notification_events(
+    mut events: Vec<ManipulationTarget>,
+    id: &str,
+    layers: &[String],
+    labels: &[(String, String)],
+    remaining: &str,
+) -> Vec<ManipulationTarget> {
+    let label = |layer: &String| {
+        labels
+            .iter()
+            .find(|(labeled, _)| labeled == layer)
+            .map(|(_, label)| label)
+    };
+    let changes: Vec<(&String, bool)> = events
+        .iter()
+        .filter_map(|event| layer_change(event, layers))
+        .collect();
+
+    let entered_label = changes
+        .iter()
+        .rev()
+        .find(|(_, active)| *active)
+        .and_then(|(layer, _)| label(layer));
+    let left_labeled = changes
+        .iter()
+        .any(|(layer, active)| !active && label(layer).is_some());
+
+    let text = match (entered_label, left_labeled) {
+        (Some(label), _) => label.clone(),
+        (None, true) => remaining.to_string(),
+        (None, false) => return events,
+    };
+    events.push(ManipulationTarget::NotificationMessage(
+        NotificationMessage::new(id.to_string(), text),
+    ));
+    events
+}
+
+/// Returns the layer whose variable is set by the event and whether it becomes active.
+fn layer_change<'a>(
+    event: &'a ManipulationTarget,
+    layers: &[String],
+) -> Option<(&'a String, bool)> {
+    match event {
+        ManipulationTarget::SetVariable(v) if layers.contains(&v.set_variable.name) => Some((
+            &v.set_variable.name,
+            v.set_variable.value != VariableValue::Integer(0),
+        )),
+        _ => None,
     }
 }
 
+fn set_target_layer(target_layer: Option<String>, source_layer: String) -> Option<DelayedAction> {
+    target_layer.map(|layer| DelayedAction::set_layer(layer, source_layer))
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct Condition {
     pub name: String,
     #[serde(rename = "type")]
     pub condition_type: String,
-    pub value: i32,
+    pub value: VariableValue,
 }
 
 impl Condition {
@@ -194,47 +879,159 @@ impl Condition {
         Condition {
             name,
             condition_type: "variable_if".to_string(),
-            value: 1,
+            value: 1.into(),
         }
     }
     pub fn inactive(name: String) -> Self {
         Condition {
             name,
             condition_type: "variable_if".to_string(),
-            value: 0,
+            value: 0.into(),
+        }
+    }
+
+    /// Condition on the single layer variable which is used for exclusive layers.
+    fn exclusive(self, variable: &str) -> Self {
+        let condition_type = match self.value {
+            VariableValue::Integer(0) => "variable_unless",
+            _ => "variable_if",
+        };
+        Condition {
+            name: variable.to_string(),
+            condition_type: condition_type.to_string(),
+            value: self.name.into(),
         }
     }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
-pub struct SimpleKeyMapping {
-    pub key_code: Key,
+#[serde(untagged)]
+pub enum VariableValue {
+    Integer(i32),
+    String(String),
+}
+
+impl From<i32> for VariableValue {
+    fn from(value: i32) -> Self {
+        VariableValue::Integer(value)
+    }
+}
+
+impl From<String> for VariableValue {
+    fn from(value: String) -> Self {
+        VariableValue::String(value)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct SimpleKeyMapping {
+    #[serde(flatten)]
+    pub key_code: KeyCode,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
 pub struct Modifiers {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub mandatory: Option<Vec<Key>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub optional: Option<Vec<Key>>,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+impl Modifiers {
+    /// Accepts the key together with any combination of modifiers.
+    pub fn any() -> Self {
+        Modifiers {
+            mandatory: None,
+            optional: Some(vec![Key::Any]),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
 pub struct FromKeyMapping {
-    pub key_code: Key,
+    #[serde(flatten)]
+    pub key_code: Option<KeyCode>,
+    /// Keys which have to be pressed together instead of a single key.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub simultaneous: Option<Vec<SimpleKeyMapping>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub simultaneous_options: Option<SimultaneousOptions>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub modifiers: Option<Modifiers>,
 }
 
+impl FromKeyMapping {
+    pub fn with_any_modifiers(key: Key) -> Self {
+        FromKeyMapping {
+            key_code: Some(key.into()),
+            modifiers: Some(Modifiers::any()),
+            ..Default::default()
+        }
+    }
+}
+
+impl std::fmt::Display for FromKeyMapping {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.key_code, &self.simultaneous) {
+            (Some(key_code), _) => write!(f, "{}", key_code),
+            (None, Some(keys)) => {
+                let keys: Vec<String> = keys.iter().map(|k| k.key_code.to_string()).collect();
+                write!(f, "{}", keys.join(&SIMULTANEOUS_SEPARATOR.to_string()))
+            }
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct SimultaneousOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key_down_order: Option<KeyDownOrder>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct ToKeyMapping {
-    pub key_code: Key,
+    #[serde(flatten)]
+    pub key_code: KeyCode,
     pub modifiers: Vec<Key>,
+    #[serde(flatten)]
+    pub options: ToEventOptions,
+}
+
+impl std::fmt::Display for ToKeyMapping {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let keys: Vec<String> = self
+            .modifiers
+            .iter()
+            .map(Key::to_string)
+            .chain(std::iter::once(self.key_code.to_string()))
+            .collect();
+        write!(f, "{}", keys.join("+"))
+    }
+}
+
+/// Optional flags of a to event.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct ToEventOptions {
+    /// Keeps the key pressed for the given time before the next event is sent.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hold_down_milliseconds: Option<u32>,
+    /// Cancels the events which would otherwise follow, such as `to_if_alone` after a
+    /// `to_if_held_down` event, `to_after_key_up` and a pending `to_delayed_action`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub halt: Option<bool>,
+    /// Whether the key repeats while it is held down.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repeat: Option<bool>,
+    /// Sends a modifier only together with the next key.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lazy: Option<bool>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct SetVariableValues {
     pub name: String,
-    pub value: i32,
+    pub value: VariableValue,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
@@ -242,9 +1039,12 @@ pub struct SetVariable {
     set_variable: SetVariableValues,
 }
 impl SetVariable {
-    pub fn new(name: String, value: i32) -> Self {
+    pub fn new(name: String, value: impl Into<VariableValue>) -> Self {
         SetVariable {
-            set_variable: SetVariableValues { name, value },
+            set_variable: SetVariableValues {
+                name,
+                value: value.into(),
+            },
         }
     }
 }
@@ -254,12 +1054,110 @@ pub struct ShellCommand {
     pub shell_command: String,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct SoftwareFunction {
+    pub software_function: SoftwareFunctionValues,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SoftwareFunctionValues {
+    OpenApplication(OpenApplication),
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct OpenApplication {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bundle_identifier: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_path: Option<String>,
+}
+
+/// Values are regular expressions which karabiner matches against the available input sources.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct InputSourceValues {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub input_source_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct InputSource {
+    pub select_input_source: InputSourceValues,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct MouseKeyValues {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub x: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub y: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vertical_wheel: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub horizontal_wheel: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub speed_multiplier: Option<serde_json::Number>,
+}
+
+/// Moves the pointer or scrolls while the key is pressed.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct MouseKey {
+    pub mouse_key: MouseKeyValues,
+}
+
+/// Maps the name of a modifier to the change of its sticky state.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct StickyModifier {
+    pub sticky_modifier: HashMap<String, StickyModifierMode>,
+}
+
+impl StickyModifier {
+    pub fn new(modifier: Key, mode: StickyModifierMode) -> Self {
+        StickyModifier {
+            sticky_modifier: HashMap::from([(modifier.to_string(), mode)]),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct PointingButton {
+    pub pointing_button: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct NotificationMessageValues {
+    pub id: String,
+    pub text: String,
+}
+
+/// Shows a message on the screen. An empty text hides the message with the same id.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct NotificationMessage {
+    set_notification_message: NotificationMessageValues,
+}
+
+impl NotificationMessage {
+    pub fn new(id: String, text: String) -> Self {
+        NotificationMessage {
+            set_notification_message: NotificationMessageValues { id, text },
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 #[serde(untagged)]
 pub enum ManipulationTarget {
     KeyMapping(ToKeyMapping),
     SetVariable(SetVariable),
     ShellCommand(ShellCommand),
+    SoftwareFunction(SoftwareFunction),
+    NotificationMessage(NotificationMessage),
+    MouseKey(MouseKey),
+    PointingButton(PointingButton),
+    InputSource(InputSource),
+    StickyModifier(StickyModifier),
 }
 
 impl ManipulationTarget {
@@ -269,27 +1167,49 @@ impl ManipulationTarget {
     pub fn set_inactive(name: String) -> Self {
         ManipulationTarget::SetVariable(SetVariable::new(name, 0))
     }
+
+    /// Enters the target layer and leaves the source layer. The base layer has no variable, so
+    /// nothing is set for it.
+    pub fn change_layer(target_layer: String, source_layer: String) -> Vec<Self> {
+        let mut targets = vec![];
+        if target_layer != BASE_LAYER {
+            targets.push(ManipulationTarget::set_active(target_layer));
+        }
+        if source_layer != BASE_LAYER {
+            targets.push(ManipulationTarget::set_inactive(source_layer));
+        }
+        targets
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct DelayedAction {
-    pub to_if_canceled: Vec<SetVariable>,
-    pub to_if_invoked: Vec<SetVariable>,
+    pub to_if_canceled: Vec<ManipulationTarget>,
+    pub to_if_invoked: Vec<ManipulationTarget>,
 }
 
 impl DelayedAction {
     fn set_layer(target_layer: String, source_layer: String) -> Self {
-        let mut actions = vec![SetVariable::new(source_layer, 0)];
-
-        if target_layer != BASE_LAYER {
-            actions.insert(0, SetVariable::new(target_layer, 1));
-        }
-
         DelayedAction {
             to_if_canceled: vec![],
-            to_if_invoked: actions,
+            to_if_invoked: ManipulationTarget::change_layer(target_layer, source_layer),
         }
     }
+
+    /// Adds the events of `other` which are not sent already.
+    fn merge(mut self, other: &DelayedAction) -> Self {
+        for (events, other_events) in [
+            (&mut self.to_if_canceled, &other.to_if_canceled),
+            (&mut self.to_if_invoked, &other.to_if_invoked),
+        ] {
+            for event in other_events {
+                if !events.contains(event) {
+                    events.push(event.clone());
+                }
+            }
+        }
+        self
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
@@ -318,5 +1238,13 @@ pub struct DeviceIdentifiers {
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct SimpleModification {
     pub from: SimpleKeyMapping,
-    pub to: Vec<SimpleKeyMapping>,
+    pub to: Vec<SimpleToKeyMapping>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct SimpleToKeyMapping {
+    #[serde(flatten)]
+    pub key_code: KeyCode,
+    #[serde(flatten)]
+    pub options: ToEventOptions,
 }
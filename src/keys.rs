@@ -1,6 +1,10 @@
+use std::str::FromStr;
+
 use serde::{Deserialize, Serialize, Serializer};
 
-#[derive(Debug, Deserialize, strum::Display, strum::EnumString, Clone, PartialEq, Eq)]
+#[derive(
+    Debug, Deserialize, strum::Display, strum::EnumString, strum::EnumIter, Clone, PartialEq, Eq,
+)]
 #[serde(rename_all = "snake_case")]
 #[strum(serialize_all = "snake_case")]
 pub enum Key {
@@ -18,6 +22,13 @@ pub enum Key {
     // Modifier Keys
     CapsLock,
 
+    // Modifier Only Keys
+    Any,
+    Command,
+    Control,
+    Option,
+    Shift,
+
     // Control or Symbol Keys
     ReturnOrEnter,
     Escape,
@@ -209,29 +220,315 @@ pub enum Key {
 
     // To Only Keys
     VkNone,
-    VkConsumerBrightnessDown,
-    VkConsumerBrightnessUp,
     VkMissionControl,
     VkLaunchpad,
     VkDashboard,
-    VkConsumerIlluminationDown,
-    VkConsumerIlluminationUp,
-    VkConsumerPrevious,
-    VkConsumerPlay,
-    VkConsumerNext,
+    #[strum(
+        to_string = "display_brightness_decrement",
+        serialize = "vk_consumer_brightness_down"
+    )]
     DisplayBrightnessDecrement,
+    #[strum(
+        to_string = "display_brightness_increment",
+        serialize = "vk_consumer_brightness_up"
+    )]
     DisplayBrightnessIncrement,
-    Rewind,
-    PlayOrPause,
-    Fastforward,
     AppleDisplayBrightnessDecrement,
     AppleDisplayBrightnessIncrement,
     AppleTopCaseDisplayBrightnessDecrement,
     AppleTopCaseDisplayBrightnessIncrement,
+    #[strum(
+        to_string = "illumination_decrement",
+        serialize = "vk_consumer_illumination_down"
+    )]
     IlluminationDecrement,
+    #[strum(
+        to_string = "illumination_increment",
+        serialize = "vk_consumer_illumination_up"
+    )]
     IlluminationIncrement,
 }
 
+/// Media and system keys which karabiner sends and receives as `consumer_key_code`.
+#[derive(
+    Debug,
+    Serialize,
+    Deserialize,
+    strum::Display,
+    strum::EnumString,
+    strum::EnumIter,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum ConsumerKey {
+    #[strum(to_string = "play_or_pause", serialize = "vk_consumer_play")]
+    PlayOrPause,
+    #[strum(to_string = "scan_next_track", serialize = "vk_consumer_next")]
+    ScanNextTrack,
+    #[strum(to_string = "scan_previous_track", serialize = "vk_consumer_previous")]
+    ScanPreviousTrack,
+    #[strum(to_string = "fast_forward", serialize = "fastforward")]
+    FastForward,
+    Rewind,
+    Eject,
+    Dictation,
+    AcSearch,
+    AlTerminalLockOrScreensaver,
+}
+
+/// Key of a from or to event, which karabiner distinguishes by the name of the field.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub enum KeyCode {
+    #[serde(rename = "key_code")]
+    Key(Key),
+    #[serde(rename = "consumer_key_code")]
+    Consumer(ConsumerKey),
+}
+
+impl KeyCode {
+    pub fn is_modifier(&self) -> bool {
+        matches!(self, KeyCode::Key(key) if key.is_modifier())
+    }
+
+    pub fn is_modifier_only(&self) -> bool {
+        matches!(self, KeyCode::Key(key) if key.is_modifier_only())
+    }
+
+    pub fn as_key(&self) -> Option<&Key> {
+        match self {
+            KeyCode::Key(key) => Some(key),
+            KeyCode::Consumer(_) => None,
+        }
+    }
+}
+
+/// Modifiers which `hyper` stands for.
+pub const HYPER: [Key; 4] = [
+    Key::LeftCommand,
+    Key::LeftShift,
+    Key::LeftOption,
+    Key::LeftControl,
+];
+/// Modifiers which `meh` stands for.
+pub const MEH: [Key; 3] = [Key::LeftShift, Key::LeftOption, Key::LeftControl];
+
+/// Separates the keys of a combo which have to be pressed simultaneously.
+pub const SIMULTANEOUS_SEPARATOR: char = '&';
+
+/// Parses keys separated by `+` and expands the `hyper` and `meh` aliases. Fails if a modifier only
+/// key would have to be sent as key code because no other key is pressed, for `any`, which is only
+/// valid in the optional modifiers of a `from` event, and for consumer keys together with other
+/// keys which are not modifiers, since consumer keys can not be held as modifiers.
+pub fn parse_keys(s: &str) -> Result<Vec<KeyCode>, strum::ParseError> {
+    let mut keys: Vec<KeyCode> = vec![];
+    for name in s.split('+') {
+        match name {
+            "hyper" => keys.extend(HYPER.map(KeyCode::from)),
+            "meh" => keys.extend(MEH.map(KeyCode::from)),
+            _ => keys.push(name.parse()?),
+        }
+    }
+
+    let mut non_modifiers = keys.iter().filter(|k| !k.is_modifier());
+    let has_consumer_key = keys.iter().any(|k| k.as_key().is_none());
+    match keys.iter().find(|k| !k.is_modifier()).or(keys.first()) {
+        _ if keys.contains(&Key::Any.into()) => Err(strum::ParseError::VariantNotFound),
+        _ if has_consumer_key && non_modifiers.nth(1).is_some() => {
+            Err(strum::ParseError::VariantNotFound)
+        }
+        Some(key) if key.is_modifier_only() => Err(strum::ParseError::VariantNotFound),
+        _ => Ok(keys),
+    }
+}
+
+impl FromStr for KeyCode {
+    type Err = strum::ParseError;
+
+    /// Keyboard keys take precedence over consumer keys with the same name.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Key::from_str(s)
+            .map(KeyCode::Key)
+            .or_else(|_| ConsumerKey::from_str(s).map(KeyCode::Consumer))
+    }
+}
+
+impl std::fmt::Display for KeyCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KeyCode::Key(key) => key.fmt(f),
+            KeyCode::Consumer(key) => key.fmt(f),
+        }
+    }
+}
+
+impl From<Key> for KeyCode {
+    fn from(key: Key) -> Self {
+        KeyCode::Key(key)
+    }
+}
+
+impl From<ConsumerKey> for KeyCode {
+    fn from(key: ConsumerKey) -> Self {
+        KeyCode::Consumer(key)
+    }
+}
+
+impl PartialEq<Key> for KeyCode {
+    fn eq(&self, other: &Key) -> bool {
+        self.as_key() == Some(other)
+    }
+}
+
+impl Key {
+    /// Keys which karabiner treats as modifiers.
+    pub fn is_modifier(&self) -> bool {
+        matches!(
+            self,
+            Key::LeftControl
+                | Key::LeftShift
+                | Key::LeftOption
+                | Key::LeftCommand
+                | Key::RightControl
+                | Key::RightShift
+                | Key::RightOption
+                | Key::RightCommand
+                | Key::Fn
+                | Key::Any
+                | Key::Command
+                | Key::Control
+                | Key::Option
+                | Key::Shift
+        )
+    }
+
+    /// Modifiers which match either side and can only be used as modifiers, not as key code.
+    pub fn is_modifier_only(&self) -> bool {
+        matches!(
+            self,
+            Key::Any | Key::Command | Key::Control | Key::Option | Key::Shift
+        )
+    }
+
+    /// Keys which type a character.
+    pub fn is_character(&self) -> bool {
+        matches!(
+            self,
+            Key::A
+                | Key::B
+                | Key::C
+                | Key::D
+                | Key::E
+                | Key::F
+                | Key::G
+                | Key::H
+                | Key::I
+                | Key::J
+                | Key::K
+                | Key::L
+                | Key::M
+                | Key::N
+                | Key::O
+                | Key::P
+                | Key::Q
+                | Key::R
+                | Key::S
+                | Key::T
+                | Key::U
+                | Key::V
+                | Key::W
+                | Key::X
+                | Key::Y
+                | Key::Z
+                | Key::Key1
+                | Key::Key2
+                | Key::Key3
+                | Key::Key4
+                | Key::Key5
+                | Key::Key6
+                | Key::Key7
+                | Key::Key8
+                | Key::Key9
+                | Key::Key0
+                | Key::Spacebar
+                | Key::Hyphen
+                | Key::EqualSign
+                | Key::OpenBracket
+                | Key::CloseBracket
+                | Key::Backslash
+                | Key::Semicolon
+                | Key::Quote
+                | Key::GraveAccentAndTilde
+                | Key::Comma
+                | Key::Period
+                | Key::Slash
+        )
+    }
+
+    /// Keys which are valid both as `from` and as `to` event and can therefore be passed through
+    /// unchanged.
+    pub fn is_passthrough(&self) -> bool {
+        !self.is_modifier() && !self.is_from_only() && !self.is_to_only()
+    }
+
+    fn is_from_only(&self) -> bool {
+        matches!(
+            self,
+            Key::F21
+                | Key::F22
+                | Key::F23
+                | Key::F24
+                | Key::Execute
+                | Key::Menu
+                | Key::Select
+                | Key::Stop
+                | Key::Again
+                | Key::Undo
+                | Key::Cut
+                | Key::Copy
+                | Key::Paste
+                | Key::Find
+                | Key::KeypadEqualSignAs400
+                | Key::LockingCapsLock
+                | Key::LockingNumLock
+                | Key::LockingScrollLock
+                | Key::AlternateErase
+                | Key::SysReqOrAttention
+                | Key::Cancel
+                | Key::Clear
+                | Key::Prior
+                | Key::Return
+                | Key::Separator
+                | Key::Out
+                | Key::Oper
+                | Key::ClearOrAgain
+                | Key::CrSelOrProps
+                | Key::ExSel
+        )
+    }
+
+    fn is_to_only(&self) -> bool {
+        matches!(
+            self,
+            Key::VkNone
+                | Key::VkMissionControl
+                | Key::VkLaunchpad
+                | Key::VkDashboard
+                | Key::DisplayBrightnessDecrement
+                | Key::DisplayBrightnessIncrement
+                | Key::AppleDisplayBrightnessDecrement
+                | Key::AppleDisplayBrightnessIncrement
+                | Key::AppleTopCaseDisplayBrightnessDecrement
+                | Key::AppleTopCaseDisplayBrightnessIncrement
+                | Key::IlluminationDecrement
+                | Key::IlluminationIncrement
+        )
+    }
+}
+
 impl Serialize for Key {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -288,3 +585,271 @@ impl Serialize for Key {
         }
     }
 }
+
+/// Keys which trigger a binding or a layer. Several keys are pressed simultaneously, modifiers have
+/// to be held down.
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct KeyCombo {
+    pub keys: Vec<KeyCode>,
+    pub modifiers: Vec<Key>,
+    /// Order in which simultaneous keys have to be pressed.
+    pub key_down_order: Option<KeyDownOrder>,
+    /// Milliseconds within which simultaneous keys have to be pressed.
+    pub threshold: Option<u32>,
+}
+
+impl KeyCombo {
+    /// Combos without keys are used for layers which are only entered from other layers.
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    pub fn is_simultaneous(&self) -> bool {
+        self.keys.len() > 1
+    }
+
+    /// The key if the combo consists of a single keyboard key without modifiers.
+    pub fn as_key(&self) -> Option<&Key> {
+        match (self.keys.as_slice(), self.modifiers.is_empty()) {
+            ([key], true) => key.as_key(),
+            _ => None,
+        }
+    }
+}
+
+impl From<Vec<KeyCode>> for KeyCombo {
+    /// The key which is not a modifier is pressed while the others are held as modifiers. If all
+    /// keys are modifiers, the first one is pressed.
+    fn from(mut keys: Vec<KeyCode>) -> Self {
+        let position = keys.iter().position(|k| !k.is_modifier()).unwrap_or(0);
+        let key = keys.remove(position);
+
+        KeyCombo {
+            keys: vec![key],
+            modifiers: keys.iter().filter_map(KeyCode::as_key).cloned().collect(),
+            ..Default::default()
+        }
+    }
+}
+
+impl From<Key> for KeyCombo {
+    fn from(key: Key) -> Self {
+        vec![KeyCode::from(key)].into()
+    }
+}
+
+impl FromStr for KeyCombo {
+    type Err = strum::ParseError;
+
+    /// Keys separated by [`SIMULTANEOUS_SEPARATOR`] are pressed simultaneously, each of them can be
+    /// combined with modifiers which are held for the whole combo, e.g. `left_command+j&k`. Keys
+    /// joined with `+` can only contain one key which is not a modifier.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut combo = KeyCombo::default();
+        for keys in s.split(SIMULTANEOUS_SEPARATOR) {
+            let keys = parse_keys(keys)?;
+            if keys.iter().filter(|k| !k.is_modifier()).count() > 1 {
+                return Err(strum::ParseError::VariantNotFound);
+            }
+            let part = KeyCombo::from(keys);
+            combo.keys.extend(part.keys);
+            combo.modifiers.extend(part.modifiers);
+        }
+        Ok(combo)
+    }
+}
+
+impl std::fmt::Display for KeyCombo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let keys: Vec<String> = self.keys.iter().map(KeyCode::to_string).collect();
+        let modifiers = self.modifiers.iter().map(|m| format!("{}+", m));
+        write!(
+            f,
+            "{}{}",
+            modifiers.collect::<String>(),
+            keys.join(&SIMULTANEOUS_SEPARATOR.to_string())
+        )
+    }
+}
+
+impl PartialEq<Key> for KeyCombo {
+    fn eq(&self, other: &Key) -> bool {
+        self.as_key() == Some(other)
+    }
+}
+
+#[derive(
+    Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, strum::Display, strum::EnumString,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum KeyDownOrder {
+    Insensitive,
+    Strict,
+    StrictInverse,
+}
+
+/// Change of the sticky state of a modifier.
+#[derive(
+    Debug,
+    Serialize,
+    Deserialize,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    strum::Display,
+    strum::EnumString,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum StickyModifierMode {
+    On,
+    Off,
+    #[default]
+    Toggle,
+}
+
+/// Keyboard layout which is used to translate text into key presses.
+#[derive(
+    Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq, strum::Display, strum::EnumString,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum KeyboardLayout {
+    #[default]
+    Us,
+    /// German layout of macOS.
+    De,
+}
+
+impl KeyboardLayout {
+    /// Returns the key which types the character, followed by the modifiers it needs. Returns
+    /// `None` if the character can not be typed with the layout.
+    pub fn keystroke(&self, character: char) -> Option<Vec<Key>> {
+        match self {
+            KeyboardLayout::Us => Self::us_keystroke(character),
+            KeyboardLayout::De => Self::de_keystroke(character),
+        }
+    }
+
+    fn us_keystroke(character: char) -> Option<Vec<Key>> {
+        let (key, shift) = match character {
+            'a'..='z' => (Key::from_str(&character.to_string()).ok()?, false),
+            'A'..='Z' => (
+                Key::from_str(&character.to_lowercase().to_string()).ok()?,
+                true,
+            ),
+            '0'..='9' => (Key::from_str(&format!("key{}", character)).ok()?, false),
+            ' ' => (Key::Spacebar, false),
+            '\n' => (Key::ReturnOrEnter, false),
+            '\t' => (Key::Tab, false),
+            '-' => (Key::Hyphen, false),
+            '_' => (Key::Hyphen, true),
+            '=' => (Key::EqualSign, false),
+            '+' => (Key::EqualSign, true),
+            '[' => (Key::OpenBracket, false),
+            '{' => (Key::OpenBracket, true),
+            ']' => (Key::CloseBracket, false),
+            '}' => (Key::CloseBracket, true),
+            '\\' => (Key::Backslash, false),
+            '|' => (Key::Backslash, true),
+            ';' => (Key::Semicolon, false),
+            ':' => (Key::Semicolon, true),
+            '\'' => (Key::Quote, false),
+            '"' => (Key::Quote, true),
+            '`' => (Key::GraveAccentAndTilde, false),
+            '~' => (Key::GraveAccentAndTilde, true),
+            ',' => (Key::Comma, false),
+            '<' => (Key::Comma, true),
+            '.' => (Key::Period, false),
+            '>' => (Key::Period, true),
+            '/' => (Key::Slash, false),
+            '?' => (Key::Slash, true),
+            '!' => (Key::Key1, true),
+            '@' => (Key::Key2, true),
+            '#' => (Key::Key3, true),
+            '$' => (Key::Key4, true),
+            '%' => (Key::Key5, true),
+            '^' => (Key::Key6, true),
+            '&' => (Key::Key7, true),
+            '*' => (Key::Key8, true),
+            '(' => (Key::Key9, true),
+            ')' => (Key::Key0, true),
+            _ => return None,
+        };
+        match shift {
+            true => Some(vec![key, Key::LeftShift]),
+            false => Some(vec![key]),
+        }
+    }
+
+    /// Key codes name the keys of the US layout, so the German characters are typed with the keys
+    /// at the same position. Dead keys such as `^` and `´` are not supported.
+    fn de_keystroke(character: char) -> Option<Vec<Key>> {
+        const NONE: &[Key] = &[];
+        const SHIFT: &[Key] = &[Key::LeftShift];
+        const OPTION: &[Key] = &[Key::LeftOption];
+        const SHIFT_OPTION: &[Key] = &[Key::LeftShift, Key::LeftOption];
+
+        let (key, modifiers) = match character {
+            'y' => (Key::Z, NONE),
+            'Y' => (Key::Z, SHIFT),
+            'z' => (Key::Y, NONE),
+            'Z' => (Key::Y, SHIFT),
+            'a'..='z' => (Key::from_str(&character.to_string()).ok()?, NONE),
+            'A'..='Z' => (
+                Key::from_str(&character.to_lowercase().to_string()).ok()?,
+                SHIFT,
+            ),
+            '0'..='9' => (Key::from_str(&format!("key{}", character)).ok()?, NONE),
+            ' ' => (Key::Spacebar, NONE),
+            '\n' => (Key::ReturnOrEnter, NONE),
+            '\t' => (Key::Tab, NONE),
+            'ü' => (Key::OpenBracket, NONE),
+            'Ü' => (Key::OpenBracket, SHIFT),
+            'ö' => (Key::Semicolon, NONE),
+            'Ö' => (Key::Semicolon, SHIFT),
+            'ä' => (Key::Quote, NONE),
+            'Ä' => (Key::Quote, SHIFT),
+            'ß' => (Key::Hyphen, NONE),
+            '?' => (Key::Hyphen, SHIFT),
+            '+' => (Key::CloseBracket, NONE),
+            '*' => (Key::CloseBracket, SHIFT),
+            '~' => (Key::CloseBracket, OPTION),
+            '#' => (Key::Backslash, NONE),
+            '\'' => (Key::Backslash, SHIFT),
+            ',' => (Key::Comma, NONE),
+            ';' => (Key::Comma, SHIFT),
+            '.' => (Key::Period, NONE),
+            ':' => (Key::Period, SHIFT),
+            '-' => (Key::Slash, NONE),
+            '_' => (Key::Slash, SHIFT),
+            '!' => (Key::Key1, SHIFT),
+            '"' => (Key::Key2, SHIFT),
+            '§' => (Key::Key3, SHIFT),
+            '$' => (Key::Key4, SHIFT),
+            '%' => (Key::Key5, SHIFT),
+            '&' => (Key::Key6, SHIFT),
+            '/' => (Key::Key7, SHIFT),
+            '(' => (Key::Key8, SHIFT),
+            ')' => (Key::Key9, SHIFT),
+            '=' => (Key::Key0, SHIFT),
+            '[' => (Key::Key5, OPTION),
+            ']' => (Key::Key6, OPTION),
+            '|' => (Key::Key7, OPTION),
+            '{' => (Key::Key8, OPTION),
+            '}' => (Key::Key9, OPTION),
+            '\\' => (Key::Key7, SHIFT_OPTION),
+            '@' => (Key::L, OPTION),
+            '€' => (Key::E, OPTION),
+            _ => return None,
+        };
+        Some(
+            std::iter::once(key)
+                .chain(modifiers.iter().cloned())
+                .collect(),
+        )
+    }
+}